@@ -6,12 +6,15 @@ extern crate getopts;
 
 use die::{Die, die};
 use std::env;
+use std::fs;
+use std::io;
+use std::io::IsTerminal;
 use std::path;
 
 /// CLI entrypoint
 fn main() {
     let brief: String = format!(
-        "Usage: {} <OPTIONS> <source directory>",
+        "Usage: {} <OPTIONS> [-T <manifest> | <source directory or archive> [<source> ...]]",
         env!("CARGO_PKG_NAME")
     );
 
@@ -19,16 +22,58 @@ fn main() {
     opts.optopt("C", "cwd", "customize current working directory", "<dir>");
     opts.optflag("c", "", "create archive (default)");
     opts.optflag("v", "verbose", "enable additional logging");
-    opts.optopt("f", "file", "output path (TGZ or TAR.GZ)", "<archive>");
+    opts.optopt(
+        "f",
+        "file",
+        "output path (TGZ, TAR.GZ, TAR.ZST, TAR.XZ, TAR.BZ2, or TAR), or - for stdout. Implies compression",
+        "<archive>",
+    );
+    opts.optflagopt(
+        "",
+        "git",
+        "archive git tracked files at a revision, else the index",
+        "<tree-ish>",
+    );
     opts.optflag("h", "help", "print usage info");
+    opts.optopt(
+        "",
+        "prefix",
+        "nest entries beneath a top level directory",
+        "<dir>",
+    );
+    opts.optflag(
+        "n",
+        "normalize",
+        "read source as an existing archive (TGZ, TAR.GZ, TAR.ZST, TAR.XZ, TAR.BZ2, or TAR)",
+    );
+    opts.optopt(
+        "T",
+        "files-from",
+        "read sources from a manifest file, or - for stdin. Listed paths archive without recursing into directories",
+        "<manifest>",
+    );
+    opts.optflag("", "null", "read NUL delimited manifest paths");
     opts.optflag("V", "version", "print version info");
-    opts.optflag("z", "", "apply gzip compression (default)");
+    opts.optflag("z", "", "apply gzip compression");
+    opts.optflag("J", "", "apply xz compression");
+    opts.optflag("j", "", "apply bzip2 compression");
+    opts.optflag("", "uncompressed", "disable compression");
+    opts.optflag("", "zstd", "apply zstd compression");
+    opts.optopt("", "zstd-level", "zstd compression level", "<level>");
+    opts.optopt(
+        "",
+        "zstd-long",
+        "zstd long distance matching window (log2 bytes)",
+        "<window>",
+    );
 
     let usage: String = opts.usage(&brief);
     let arguments: Vec<String> = env::args().collect();
     let optmatches: getopts::Matches = opts.parse(&arguments[1..]).die(&usage);
 
     let verbose = optmatches.opt_present("v");
+    let normalize = optmatches.opt_present("n");
+    let null = optmatches.opt_present("null");
 
     if optmatches.opt_present("h") {
         die!(0; usage);
@@ -57,6 +102,65 @@ fn main() {
         eprintln!("debug: configuration: {:?}", ch);
     }
 
+    if optmatches.opt_present("z") {
+        ch.compression = Some(chandler::Compression::Gzip);
+    }
+
+    if optmatches.opt_present("J") {
+        ch.compression = Some(chandler::Compression::Xz { level: None });
+    }
+
+    if optmatches.opt_present("j") {
+        ch.compression = Some(chandler::Compression::Bzip2 { level: None });
+    }
+
+    if optmatches.opt_present("uncompressed") {
+        ch.compression = Some(chandler::Compression::Uncompressed);
+    }
+
+    let zstd_level_option = optmatches
+        .opt_str("zstd-level")
+        .map(|e| e.parse::<i32>().die(&usage));
+    let zstd_long_option = optmatches
+        .opt_str("zstd-long")
+        .map(|e| e.parse::<u32>().die(&usage));
+
+    if optmatches.opt_present("zstd") || zstd_level_option.is_some() || zstd_long_option.is_some() {
+        let (mut level, mut long) = (None, None);
+
+        if let Some(chandler::Compression::Zstd {
+            level: configured_level,
+            long: configured_long,
+        }) = ch.compression
+        {
+            level = configured_level;
+            long = configured_long;
+        }
+
+        ch.compression = Some(chandler::Compression::Zstd {
+            level: zstd_level_option.or(level),
+            long: zstd_long_option.or(long),
+        });
+    }
+
+    if optmatches.opt_present("git") {
+        if normalize || optmatches.opt_present("T") {
+            die!(1; "error: --git does not combine with -n or -T");
+        }
+
+        let mut git = ch.git.take().unwrap_or_default();
+
+        if let Some(revision) = optmatches.opt_str("git") {
+            git.revision = Some(revision);
+        }
+
+        ch.git = Some(git);
+    }
+
+    if let Some(prefix) = optmatches.opt_str("prefix") {
+        ch.prefix = Some(prefix);
+    }
+
     if optmatches.opt_present("C") {
         let cwd_string = optmatches.opt_str("C").die(&usage);
         ch.cwd = Some(path::PathBuf::from(cwd_string))
@@ -70,18 +174,51 @@ fn main() {
         final_archive = cwd.join(archive_path);
     }
 
+    let to_stdout = archive_string == "-";
+
     let final_archive_string = match final_archive.to_str() {
+        _ if to_stdout => "stdout",
         Some(v) => v,
         None => die!(format!("unable to render path {:?}", final_archive)),
     };
 
+    let manifest_option = optmatches.opt_str("T");
     let args = optmatches.free;
 
-    if args.len() != 1 {
+    if args.is_empty() == manifest_option.is_none() {
         die!(1; usage);
     }
 
-    match ch.archive(archive_path, path::Path::new(&args[0])) {
+    let sources: Vec<path::PathBuf> = match manifest_option.as_deref() {
+        None => Ok(args.iter().map(path::PathBuf::from).collect()),
+        Some("-") => chandler::read_manifest(io::stdin().lock(), null),
+        Some(manifest) => fs::File::open(manifest).and_then(|e| chandler::read_manifest(e, null)),
+    }
+    .unwrap_or_else(|e| die!(1; "error: unable to read manifest: {}", e));
+
+    let result = if to_stdout {
+        if io::stdout().is_terminal() {
+            die!("refusing to write archive data to a terminal");
+        }
+
+        let stdout = io::BufWriter::new(io::stdout().lock());
+
+        if normalize {
+            ch.normalize_to(stdout, &sources)
+        } else if manifest_option.is_some() {
+            ch.archive_paths_to(stdout, &sources)
+        } else {
+            ch.archive_to(stdout, &sources)
+        }
+    } else if normalize {
+        ch.normalize(archive_path, &sources)
+    } else if manifest_option.is_some() {
+        ch.archive_paths(archive_path, &sources)
+    } else {
+        ch.archive(archive_path, &sources)
+    };
+
+    match result {
         Err(e) => die!(e.to_string()),
         _ => eprintln!("archived entries to {final_archive_string}"),
     }
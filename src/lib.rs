@@ -1,21 +1,37 @@
 //! chandler assembles tape archives.
 
+extern crate bzip2;
 extern crate fancy_regex;
 extern crate flate2;
+#[cfg(unix)]
+extern crate libc;
+extern crate liblzma;
 extern crate normalize_path;
 extern crate serde;
 extern crate tar;
 extern crate toml;
 extern crate walkdir;
+extern crate zstd;
 
 use normalize_path::NormalizePath;
 use serde::{Deserialize, Serialize};
 
+use std::collections;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
+use std::ffi;
+use std::fmt;
 use std::fs;
+use std::hash;
+use std::hash::Hasher;
 use std::io;
+use std::mem;
 use std::path;
+use std::process;
+use std::str;
 use std::sync;
+use std::thread;
 use std::time;
 
 /// CONFIGURATION_FILENAME denotes the file path to an optional TOML configuration file,
@@ -44,6 +60,19 @@ pub fn generate_skip_path_pattern(
     )
 }
 
+/// generate_include_path_pattern converts a collection of include paths to a regex.
+pub fn generate_include_path_pattern(
+    include_paths: &[String],
+) -> Result<fancy_regex::Regex, fancy_regex::Error> {
+    fancy_regex::Regex::new(
+        &include_paths
+            .iter()
+            .map(|e| format!("(?:{e})"))
+            .collect::<Vec<String>>()
+            .join("|"),
+    )
+}
+
 #[test]
 fn test_default_skip_paths() -> Result<(), fancy_regex::Error> {
     let pattern = generate_skip_path_pattern(&DEFAULT_SKIP_PATHS)?;
@@ -57,6 +86,258 @@ fn test_default_skip_paths() -> Result<(), fancy_regex::Error> {
     Ok(())
 }
 
+/// DEFAULT_IGNORE_FILES collects the names of ignore files consulted by default.
+pub static DEFAULT_IGNORE_FILES: sync::LazyLock<Vec<String>> =
+    sync::LazyLock::new(|| vec![".chandlerignore".to_string()]);
+
+/// IgnorePattern models a single line of a gitignore style file.
+#[derive(Debug)]
+pub struct IgnorePattern {
+    /// regex matches paths relative to the directory containing the ignore file.
+    pub regex: fancy_regex::Regex,
+
+    /// negate re-includes matching paths.
+    pub negate: bool,
+
+    /// directory restricts matches to directories.
+    pub directory: bool,
+}
+
+/// glob_to_regex converts a gitignore style glob to regex syntax.
+fn glob_to_regex(glob: &str) -> String {
+    let segments: Vec<&str> = glob.split('/').collect();
+    let mut out = String::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i + 1 == segments.len();
+
+        if *segment == "**" {
+            out.push_str(if last { ".*" } else { "(?:.*/)?" });
+            continue;
+        }
+
+        let chars: Vec<char> = segment.chars().collect();
+        let mut j = 0;
+
+        while j < chars.len() {
+            match chars[j] {
+                '\\' if j + 1 < chars.len() => {
+                    j += 1;
+                    out.push_str(&fancy_regex::escape(&chars[j].to_string()));
+                }
+                '*' => out.push_str("[^/]*"),
+                '?' => out.push_str("[^/]"),
+                '[' => {
+                    let start = j + 1;
+                    let mut end = start;
+
+                    if end < chars.len() && matches!(chars[end], '!' | '^') {
+                        end += 1;
+                    }
+
+                    if end < chars.len() && chars[end] == ']' {
+                        end += 1;
+                    }
+
+                    while end < chars.len() && chars[end] != ']' {
+                        end += 1;
+                    }
+
+                    if end >= chars.len() {
+                        out.push_str("\\[");
+                    } else {
+                        let class: String = chars[start..end].iter().collect();
+                        out.push('[');
+                        out.push_str(&match class.strip_prefix('!') {
+                            Some(rest) => format!("^{}", rest.replace('\\', "\\\\")),
+                            None => class.replace('\\', "\\\\"),
+                        });
+                        out.push(']');
+                        j = end;
+                    }
+                }
+                c => out.push_str(&fancy_regex::escape(&c.to_string())),
+            }
+
+            j += 1;
+        }
+
+        if !last {
+            out.push('/');
+        }
+    }
+
+    out
+}
+
+/// parse_ignore_line converts a line of a gitignore style file to a pattern.
+///
+/// Blank lines and comments yield None.
+pub fn parse_ignore_line(line: &str) -> Result<Option<IgnorePattern>, fancy_regex::Error> {
+    let mut line = line.trim_end_matches('\r').to_string();
+
+    if line.starts_with('#') {
+        return Ok(None);
+    }
+
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line.pop();
+    }
+
+    let (negate, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.as_str()),
+    };
+
+    let (directory, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    // Patterns with an inner slash anchor to the directory containing the ignore file.
+    let (anchor, line) = match line.strip_prefix('/') {
+        Some(rest) => ("^", rest),
+        None if line.contains('/') => ("^", line),
+        None => ("^(?:.*/)?", line),
+    };
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(IgnorePattern {
+        regex: fancy_regex::Regex::new(&format!("{anchor}{}$", glob_to_regex(line)))?,
+        negate,
+        directory,
+    }))
+}
+
+/// load_ignore_file parses a gitignore style file.
+pub fn load_ignore_file(pth: &path::Path) -> Result<Vec<IgnorePattern>, io::Error> {
+    let mut patterns = Vec::new();
+
+    for line in fs::read_to_string(pth)?.lines() {
+        if let Some(pattern) =
+            parse_ignore_line(line).map_err(|e| io::Error::other(e.to_string()))?
+        {
+            patterns.push(pattern);
+        }
+    }
+
+    Ok(patterns)
+}
+
+/// is_ignored evaluates a path against ignore files from enclosing directories,
+/// ordered from outermost to innermost.
+///
+/// As with git, the last matching pattern wins.
+fn is_ignored(
+    ignores: &[(path::PathBuf, Vec<IgnorePattern>)],
+    pth: &path::Path,
+    is_dir: bool,
+) -> Result<bool, io::Error> {
+    let mut ignored = false;
+
+    for (dir, patterns) in ignores {
+        let Some(relative) = pth.strip_prefix(dir).ok().and_then(|e| e.to_str()) else {
+            continue;
+        };
+
+        if relative.is_empty() {
+            continue;
+        }
+
+        for pattern in patterns {
+            if pattern.directory && !is_dir {
+                continue;
+            }
+
+            if pattern
+                .regex
+                .is_match(relative)
+                .map_err(|e| io::Error::other(e.to_string()))?
+            {
+                ignored = !pattern.negate;
+            }
+        }
+    }
+
+    Ok(ignored)
+}
+
+#[test]
+fn test_is_ignored() -> Result<(), io::Error> {
+    let parse = |lines: &[&str]| -> Vec<IgnorePattern> {
+        lines
+            .iter()
+            .filter_map(|e| parse_ignore_line(e).unwrap())
+            .collect()
+    };
+    let ignores = vec![
+        (
+            path::PathBuf::from("src"),
+            parse(&[
+                "# build outputs",
+                "",
+                "*.o",
+                "!keep.o",
+                "/target/",
+                "docs/**/*.tmp",
+                "cache?",
+                "[!a]bc",
+                "\\#notes",
+            ]),
+        ),
+        (path::PathBuf::from("src/vendor"), parse(&["!*.o"])),
+    ];
+
+    assert!(is_ignored(&ignores, path::Path::new("src/main.o"), false)?);
+    assert!(is_ignored(
+        &ignores,
+        path::Path::new("src/lib/main.o"),
+        false
+    )?);
+    assert!(!is_ignored(&ignores, path::Path::new("src/keep.o"), false)?);
+    assert!(!is_ignored(
+        &ignores,
+        path::Path::new("src/vendor/zlib.o"),
+        false
+    )?);
+    assert!(is_ignored(&ignores, path::Path::new("src/target"), true)?);
+    assert!(!is_ignored(&ignores, path::Path::new("src/target"), false)?);
+    assert!(!is_ignored(
+        &ignores,
+        path::Path::new("src/lib/target"),
+        true
+    )?);
+    assert!(is_ignored(
+        &ignores,
+        path::Path::new("src/docs/a.tmp"),
+        false
+    )?);
+    assert!(is_ignored(
+        &ignores,
+        path::Path::new("src/docs/a/b/c.tmp"),
+        false
+    )?);
+    assert!(!is_ignored(
+        &ignores,
+        path::Path::new("src/lib/docs/a.tmp"),
+        false
+    )?);
+    assert!(is_ignored(&ignores, path::Path::new("src/cache1"), false)?);
+    assert!(!is_ignored(
+        &ignores,
+        path::Path::new("src/cache12"),
+        false
+    )?);
+    assert!(is_ignored(&ignores, path::Path::new("src/xbc"), false)?);
+    assert!(!is_ignored(&ignores, path::Path::new("src/abc"), false)?);
+    assert!(is_ignored(&ignores, path::Path::new("src/#notes"), false)?);
+    assert!(!is_ignored(&ignores, path::Path::new("src"), true)?);
+    Ok(())
+}
+
 /// DEFAULT_NONEXECUTABLE_FILE_PATHS matches UNIX and software development file paths
 /// which do not typically use executable permissions.
 pub static DEFAULT_NONEXECUTABLE_FILE_PATHS: &str = "(?i)^aliases|(ba|(m)?k|z)shrc|(bsd|gnu)?makefile|changelog|exports|fstab|license|readme|group|hosts|issue|mime|modules|profile|protocols|resolv|services|t(e)?mp|zshenv|((.*/)?etc/.+)$";
@@ -150,206 +431,2461 @@ pub enum HeaderType {
 /// DEFAULT_HEADER_TYPE is UStar.
 pub static DEFAULT_HEADER_TYPE: HeaderType = HeaderType::UStar;
 
-/// HeaderType models a tarball header type.
-#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
-#[serde(tag = "type")]
-pub enum FileMode {
-    /// Directory models a folder.
-    Directory,
+/// DEFAULT_ZSTD_LEVEL is the zstd library default compression level.
+pub static DEFAULT_ZSTD_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
 
-    /// File models an ordinary, non-directory file.
-    File,
+/// DEFAULT_XZ_LEVEL is the xz library default compression preset.
+pub static DEFAULT_XZ_LEVEL: u32 = 6;
+
+/// DEFAULT_BZIP2_LEVEL is the bzip2 library default block size (x 100 KiB).
+pub static DEFAULT_BZIP2_LEVEL: u32 = 9;
+
+/// Encoder models a compressing writer.
+pub trait Encoder: io::Write {
+    /// finish terminates the compressed stream,
+    /// flushing any remaining data to the underlying sink.
+    fn finish(self: Box<Self>) -> Result<(), io::Error>;
 }
 
-/// Condition models an archive entry state.
+/// Codec models a compression format.
 ///
-/// Fields with values present are intersected together (AND).
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Condition {
-    /// mode denotes an FileMode.
-    pub mode: Option<FileMode>,
-
-    /// paths matches file paths.
+/// Implement Codec to supply additional compression formats,
+/// by way of `Chandler.codec`.
+pub trait Codec: fmt::Debug {
+    /// encoder wraps a sink with compression.
     ///
-    /// Syntax is Rust [regex](https://crates.io/crates/regex).
-    pub paths: Option<String>,
+    /// For reproducibility, encoders should generate identical output for identical input.
+    fn encoder<'a>(
+        &self,
+        sink: Box<dyn io::Write + 'a>,
+    ) -> Result<Box<dyn Encoder + 'a>, io::Error>;
 }
 
-/// Rule applies given permissions for matching file patterns.
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Rule {
-    /// when denotes a condition required to apply this rule's effects.
-    pub when: Option<Condition>,
+/// Passthrough writes uncompressed streams.
+struct Passthrough<W: io::Write>(W);
 
-    /// mtime overrides entry modification timestamps (UNIX epoch).
-    pub mtime: Option<u64>,
+impl<W: io::Write> io::Write for Passthrough<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
 
-    /// uid denotes an effective user id.
-    pub uid: Option<u64>,
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
 
-    /// gid denotes an effective group id.
-    pub gid: Option<u64>,
+impl<W: io::Write> Encoder for Passthrough<W> {
+    fn finish(mut self: Box<Self>) -> Result<(), io::Error> {
+        self.0.flush()
+    }
+}
 
-    /// username denotes an effective username.
-    pub username: Option<String>,
+impl<W: io::Write> Encoder for flate2::write::GzEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<(), io::Error> {
+        flate2::write::GzEncoder::finish(*self)?.flush()
+    }
+}
 
-    /// groupname denotes an effective group name.
-    pub groupname: Option<String>,
+impl<W: io::Write> Encoder for zstd::Encoder<'static, W> {
+    fn finish(self: Box<Self>) -> Result<(), io::Error> {
+        zstd::Encoder::finish(*self)?.flush()
+    }
+}
 
-    /// permissions denotes an effective chmod mask of file permissions.
-    pub permissions: Option<u32>,
+impl<W: io::Write> Encoder for liblzma::write::XzEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<(), io::Error> {
+        liblzma::write::XzEncoder::finish(*self)?.flush()
+    }
 }
 
-/// DEFAULT_RULES implements common archive entry behaviors,
-/// such as marking most extensionless paths as chmod +x.
-pub static DEFAULT_RULES: sync::LazyLock<Vec<Rule>> = sync::LazyLock::new(|| {
-    vec![
-        Rule {
-            when: None,
-            mtime: None,
-            uid: None,
-            gid: None,
-            username: None,
-            groupname: None,
-            permissions: Some(0o755u32),
-        },
-        Rule {
-            when: Some(Condition {
-                mode: Some(FileMode::File),
-                paths: Some(DEFAULT_NONEXECUTABLE_FILE_PATHS.to_string()),
-            }),
-            mtime: None,
-            uid: None,
-            gid: None,
-            username: None,
-            groupname: None,
-            permissions: Some(0o644u32),
-        },
-        Rule {
-            when: Some(Condition {
-                mode: Some(FileMode::File),
-                paths: Some(EXTENSIONED_FILE_PATHS.to_string()),
-            }),
-            mtime: None,
-            uid: None,
-            gid: None,
-            username: None,
-            groupname: None,
-            permissions: Some(0o644u32),
+impl<W: io::Write> Encoder for bzip2::write::BzEncoder<W> {
+    fn finish(self: Box<Self>) -> Result<(), io::Error> {
+        bzip2::write::BzEncoder::finish(*self)?.flush()
+    }
+}
+
+/// Compression models the builtin tarball compression formats.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum Compression {
+    /// Uncompressed models plain tarballs (TAR).
+    Uncompressed,
+
+    /// Gzip models gzip (TGZ, TAR.GZ) compression.
+    Gzip,
+
+    /// Zstd models Zstandard (TAR.ZST) compression.
+    Zstd {
+        /// level denotes a compression level.
+        ///
+        /// Default: `DEFAULT_ZSTD_LEVEL`.
+        level: Option<i32>,
+
+        /// long enables long distance matching,
+        /// with a window size expressed as a power of two (e.g. 27 for 128 MiB).
+        ///
+        /// Archives compressed with windows beyond 27 may require
+        /// `zstd --long=<window>` or equivalent for decompression.
+        long: Option<u32>,
+    },
+
+    /// Xz models xz (TXZ, TAR.XZ) compression.
+    Xz {
+        /// level denotes a compression preset (0 - 9).
+        ///
+        /// Default: `DEFAULT_XZ_LEVEL`.
+        level: Option<u32>,
+    },
+
+    /// Bzip2 models bzip2 (TBZ2, TAR.BZ2) compression.
+    Bzip2 {
+        /// level denotes a compression level (1 - 9).
+        ///
+        /// Default: `DEFAULT_BZIP2_LEVEL`.
+        level: Option<u32>,
+    },
+}
+
+/// DEFAULT_COMPRESSION is Gzip.
+pub static DEFAULT_COMPRESSION: Compression = Compression::Gzip;
+
+/// COMPRESSION_EXTENSIONS associates archive file extensions with compression formats.
+pub static COMPRESSION_EXTENSIONS: &[(&str, Compression)] = &[
+    (".tar", Compression::Uncompressed),
+    (".tgz", Compression::Gzip),
+    (".taz", Compression::Gzip),
+    (".tar.gz", Compression::Gzip),
+    (
+        ".tzst",
+        Compression::Zstd {
+            level: None,
+            long: None,
         },
-        Rule {
-            when: Some(Condition {
-                mode: None,
-                paths: Some(SYSTEM_V_INIT_PATHS.to_string()),
-            }),
-            mtime: None,
-            uid: None,
-            gid: None,
-            username: None,
-            groupname: None,
-            permissions: Some(0o755u32),
+    ),
+    (
+        ".tar.zst",
+        Compression::Zstd {
+            level: None,
+            long: None,
         },
-    ]
-});
-
-impl Rule {
-    /// is_match determines whether a rule relates to an entry.
-    pub fn is_match(&self, filemode: &FileMode, pth: &str) -> Result<bool, io::Error> {
-        let condition_option = &self.when;
+    ),
+    (".txz", Compression::Xz { level: None }),
+    (".tar.xz", Compression::Xz { level: None }),
+    (".tbz", Compression::Bzip2 { level: None }),
+    (".tbz2", Compression::Bzip2 { level: None }),
+    (".tb2", Compression::Bzip2 { level: None }),
+    (".tar.bz2", Compression::Bzip2 { level: None }),
+];
 
-        if condition_option.is_none() {
-            return Ok(true);
+impl Compression {
+    /// name renders a human friendly format name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Uncompressed => "uncompressed",
+            Compression::Gzip => "gzip",
+            Compression::Zstd { .. } => "zstd",
+            Compression::Xz { .. } => "xz",
+            Compression::Bzip2 { .. } => "bzip2",
         }
+    }
 
-        let condition = condition_option.as_ref().unwrap();
+    /// is_kind determines whether two formats share an algorithm, disregarding tuning.
+    pub fn is_kind(&self, other: &Compression) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+    }
 
-        if let Some(when_mode) = condition.mode
-            && when_mode != *filemode
-        {
-            return Ok(false);
-        }
+    /// from_path infers a compression format from an archive file extension (case insensitive).
+    pub fn from_path(pth: &path::Path) -> Option<Compression> {
+        let filename = pth.file_name()?.to_str()?.to_lowercase();
 
-        if let Some(when_paths) = &condition.paths {
-            let pattern =
-                fancy_regex::Regex::new(when_paths).map_err(|e| io::Error::other(e.to_string()))?;
+        COMPRESSION_EXTENSIONS
+            .iter()
+            .find(|(extension, _)| filename.ends_with(extension))
+            .map(|(_, compression)| *compression)
+    }
+}
 
-            if !pattern.is_match(pth).map_err(io::Error::other)? {
-                return Ok(false);
+#[test]
+fn test_compression_from_path() {
+    assert_eq!(
+        Compression::from_path(path::Path::new("hello-1.0.0.tar")),
+        Some(Compression::Uncompressed)
+    );
+    assert_eq!(
+        Compression::from_path(path::Path::new("hello-1.0.0.TGZ")),
+        Some(Compression::Gzip)
+    );
+    assert_eq!(
+        Compression::from_path(path::Path::new("dist/hello-1.0.0.tar.gz")),
+        Some(Compression::Gzip)
+    );
+    assert!(
+        Compression::from_path(path::Path::new("hello-1.0.0.tar.zst")).is_some_and(|e| e.is_kind(
+            &Compression::Zstd {
+                level: Some(19),
+                long: None,
             }
-        }
+        ))
+    );
+    assert_eq!(
+        Compression::from_path(path::Path::new("hello-1.0.0.tar.xz")),
+        Some(Compression::Xz { level: None })
+    );
+    assert_eq!(
+        Compression::from_path(path::Path::new("hello-1.0.0.tbz2")),
+        Some(Compression::Bzip2 { level: None })
+    );
+    assert_eq!(Compression::from_path(path::Path::new("hello-1.0.0")), None);
+    assert_eq!(Compression::from_path(path::Path::new("hello.zip")), None);
+}
 
-        Ok(true)
-    }
+impl Codec for Compression {
+    /// encoder wraps a sink with compression.
+    ///
+    /// Encoders run single threaded, so that output remains reproducible.
+    fn encoder<'a>(
+        &self,
+        sink: Box<dyn io::Write + 'a>,
+    ) -> Result<Box<dyn Encoder + 'a>, io::Error> {
+        match self {
+            Compression::Uncompressed => Ok(Box::new(Passthrough(sink))),
+            Compression::Gzip => Gzip::default().encoder(sink),
+            Compression::Zstd { level, long } => {
+                let mut encoder = zstd::Encoder::new(sink, level.unwrap_or(DEFAULT_ZSTD_LEVEL))?;
+                encoder.include_checksum(true)?;
 
-    /// apply modifies headers.
-    pub fn apply(&self, header: &mut tar::Header) -> Result<(), io::Error> {
-        if let Some(mtime) = self.mtime {
-            header.set_mtime(mtime);
-        }
+                if let Some(window_log) = long {
+                    encoder.long_distance_matching(true)?;
+                    encoder.window_log(*window_log)?;
+                }
 
-        if let Some(uid) = self.uid {
-            header.set_uid(uid);
-        }
+                Ok(Box::new(encoder))
+            }
+            Compression::Xz { level } => {
+                let level = level.unwrap_or(DEFAULT_XZ_LEVEL);
 
-        if let Some(gid) = self.gid {
-            header.set_gid(gid);
-        }
+                if level > 9 {
+                    return Err(io::Error::other(format!(
+                        "xz compression level out of range (0 - 9): {level}"
+                    )));
+                }
 
-        if let Some(username) = &self.username {
-            header.set_username(username)?;
-        }
+                Ok(Box::new(liblzma::write::XzEncoder::new(sink, level)))
+            }
+            Compression::Bzip2 { level } => {
+                let level = level.unwrap_or(DEFAULT_BZIP2_LEVEL);
 
-        if let Some(groupname) = &self.groupname {
-            header.set_groupname(groupname)?;
-        }
+                if !(1..=9).contains(&level) {
+                    return Err(io::Error::other(format!(
+                        "bzip2 compression level out of range (1 - 9): {level}"
+                    )));
+                }
 
-        if let Some(permissions) = &self.permissions {
-            header.set_mode(*permissions);
+                Ok(Box::new(bzip2::write::BzEncoder::new(
+                    sink,
+                    bzip2::Compression::new(level),
+                )))
+            }
         }
-
-        Ok(())
     }
 }
 
-/// Chandler assembles gunzipped tarballs (TGZ, TAR.GZ).
-#[derive(Debug, Default, Deserialize, Serialize)]
-pub struct Chandler {
-    /// verbose enables additional logging.
-    pub verbose: Option<bool>,
+/// DEFAULT_GZIP_LEVEL is the zlib default compression level.
+pub static DEFAULT_GZIP_LEVEL: u32 = 6;
 
-    /// header denotes a tape archive format.
-    pub header: Option<HeaderType>,
+/// DEFAULT_GZIP_OS is the gzip header operating system byte for an unknown OS.
+///
+/// Unlike the gzip tool's host specific values, this remains stable across platforms.
+pub static DEFAULT_GZIP_OS: u8 = 255;
 
-    /// cwd customizes the current working directory.
-    pub cwd: Option<path::PathBuf>,
+/// Gzip customizes gzip compression.
+///
+/// Defaults generate reproducible gzip headers,
+/// omitting host specific timestamps and file names.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Gzip {
+    /// level denotes a compression level (0 - 9).
+    ///
+    /// Default: `DEFAULT_GZIP_LEVEL`.
+    pub level: Option<u32>,
 
-    /// skip_paths collects file path patterns to exclude from archival,
+    /// mtime denotes the gzip header modification timestamp (UNIX epoch).
     ///
-    /// Syntax is Rust [regex](https://crates.io/crates/regex).
-    pub skip_paths: Option<Vec<String>>,
+    /// Default: `0` (unavailable).
+    pub mtime: Option<u32>,
 
-    /// rules collects a sequence of rules to apply to inbound files.
-    pub rules: Option<Vec<Rule>>,
-}
+    /// filename denotes the gzip header original file name.
+    ///
+    /// Default: (omitted).
+    pub filename: Option<String>,
 
-/// permissions_to_u32 converts fs::Permissions objects to chmod integers.
-pub fn permissions_to_u32(permissions: fs::Permissions) -> u32 {
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        permissions.mode()
-    }
-    #[cfg(windows)]
-    {
-        if permissions.readonly() {
-            0o444u32
-        } else {
-            0o666u32
-        }
-    }
+    /// comment denotes the gzip header comment.
+    ///
+    /// Default: (omitted).
+    pub comment: Option<String>,
+
+    /// os denotes the gzip header operating system byte.
+    ///
+    /// Default: `DEFAULT_GZIP_OS`.
+    pub os: Option<u8>,
+
+    /// threads enables parallel compression (pigz style),
+    /// with a given worker thread count.
+    ///
+    /// `0` selects the available parallelism of the host.
+    ///
+    /// Default: (disabled).
+    pub threads: Option<usize>,
+
+    /// block_size denotes the uncompressed size of independently compressed blocks,
+    /// when compressing in parallel.
+    ///
+    /// Output depends on the block size, though not on the thread count.
+    /// Sizes below `MIN_GZIP_BLOCK_SIZE` present an error.
+    ///
+    /// Default: `DEFAULT_GZIP_BLOCK_SIZE`.
+    pub block_size: Option<usize>,
 }
 
-impl Chandler {
+/// DEFAULT_GZIP_BLOCK_SIZE is 128 KiB, as in pigz.
+pub static DEFAULT_GZIP_BLOCK_SIZE: usize = 128 * 1024;
+
+/// MIN_GZIP_BLOCK_SIZE is 32 KiB, as in pigz.
+pub static MIN_GZIP_BLOCK_SIZE: usize = 32 * 1024;
+
+/// gzip_header renders a gzip member header.
+fn gzip_header(
+    level: u32,
+    mtime: u32,
+    os: u8,
+    filename: Option<&str>,
+    comment: Option<&str>,
+) -> Vec<u8> {
+    let mut flags = 0u8;
+    let mut fields = Vec::new();
+
+    if let Some(filename) = filename {
+        flags |= 0x08;
+        fields.extend_from_slice(filename.as_bytes());
+        fields.push(0);
+    }
+
+    if let Some(comment) = comment {
+        flags |= 0x10;
+        fields.extend_from_slice(comment.as_bytes());
+        fields.push(0);
+    }
+
+    let extra_flags = match level {
+        9.. => 2,
+        ..=1 => 4,
+        _ => 0,
+    };
+
+    let mut header = vec![0x1f, 0x8b, 0x08, flags];
+    header.extend_from_slice(&mtime.to_le_bytes());
+    header.push(extra_flags);
+    header.push(os);
+    header.extend(fields);
+    header
+}
+
+/// deflate_block compresses a block as raw deflate data.
+///
+/// Intermediate blocks end with a sync flush, so that blocks concatenate
+/// into a single deflate stream. The last block terminates the stream.
+fn deflate_block(block: &[u8], level: u32, last: bool) -> Result<Vec<u8>, io::Error> {
+    let mut compress = flate2::Compress::new(flate2::Compression::new(level), false);
+    let flush = if last {
+        flate2::FlushCompress::Finish
+    } else {
+        flate2::FlushCompress::Sync
+    };
+    let mut output = Vec::with_capacity(block.len() / 2 + 64);
+
+    loop {
+        let consumed = compress.total_in() as usize;
+        let status = compress
+            .compress_vec(&block[consumed..], &mut output, flush)
+            .map_err(io::Error::other)?;
+        let exhausted = compress.total_in() as usize == block.len();
+
+        if last && status == flate2::Status::StreamEnd {
+            return Ok(output);
+        }
+
+        if !last && exhausted && output.len() < output.capacity() {
+            return Ok(output);
+        }
+
+        output.reserve(output.capacity().max(64));
+    }
+}
+
+/// ParallelGzEncoder compresses gzip streams on multiple threads.
+///
+/// Blocks compress independently on a pool of worker threads,
+/// concatenating in order into one gzip member.
+/// Writers proceed with further blocks while workers compress earlier ones.
+struct ParallelGzEncoder<W: io::Write> {
+    /// sink receives compressed data.
+    sink: W,
+
+    /// level denotes a compression level.
+    level: u32,
+
+    /// block_size denotes the uncompressed block size.
+    block_size: usize,
+
+    /// buffer collects data for the current block.
+    buffer: Vec<u8>,
+
+    /// jobs sends indexed blocks to workers.
+    jobs: Option<sync::mpsc::Sender<(usize, Vec<u8>)>>,
+
+    /// results receives indexed, compressed blocks from workers.
+    results: sync::mpsc::Receiver<(usize, Result<Vec<u8>, io::Error>)>,
+
+    /// workers denotes the worker threads.
+    workers: Vec<thread::JoinHandle<()>>,
+
+    /// completed holds compressed blocks arriving out of order.
+    completed: collections::BTreeMap<usize, Vec<u8>>,
+
+    /// submitted counts blocks sent to workers.
+    submitted: usize,
+
+    /// written counts compressed blocks emitted to the sink.
+    written: usize,
+
+    /// max_pending limits blocks in flight, bounding memory use.
+    max_pending: usize,
+
+    /// crc tracks the uncompressed checksum and length.
+    crc: flate2::Crc,
+}
+
+impl<W: io::Write> ParallelGzEncoder<W> {
+    /// submit sends a full block to the workers,
+    /// writing any compressed blocks ready in order.
+    fn submit(&mut self, block: Vec<u8>) -> Result<(), io::Error> {
+        self.jobs
+            .as_ref()
+            .ok_or_else(|| io::Error::other("gzip encoder finished"))?
+            .send((self.submitted, block))
+            .map_err(|_| io::Error::other("gzip workers exited"))?;
+        self.submitted += 1;
+
+        while let Ok(result) = self.results.try_recv() {
+            self.store(result)?;
+        }
+
+        while self.submitted - self.written >= self.max_pending {
+            self.receive()?;
+        }
+
+        Ok(())
+    }
+
+    /// receive awaits the next compressed block from the workers.
+    fn receive(&mut self) -> Result<(), io::Error> {
+        let result = self
+            .results
+            .recv()
+            .map_err(|_| io::Error::other("gzip workers exited"))?;
+        self.store(result)
+    }
+
+    /// store collects a compressed block, writing blocks in order.
+    fn store(&mut self, result: (usize, Result<Vec<u8>, io::Error>)) -> Result<(), io::Error> {
+        let (index, output) = result;
+        self.completed.insert(index, output?);
+
+        while let Some(output) = self.completed.remove(&self.written) {
+            self.sink.write_all(&output)?;
+            self.written += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: io::Write> io::Write for ParallelGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.crc.update(buf);
+        self.buffer.extend_from_slice(buf);
+
+        while self.buffer.len() >= self.block_size {
+            let remainder = self.buffer.split_off(self.block_size);
+            let block = mem::replace(&mut self.buffer, remainder);
+            self.submit(block)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+impl<W: io::Write> Encoder for ParallelGzEncoder<W> {
+    fn finish(mut self: Box<Self>) -> Result<(), io::Error> {
+        // Closing the job queue lets workers exit once idle.
+        drop(self.jobs.take());
+
+        while self.written < self.submitted {
+            self.receive()?;
+        }
+
+        for worker in self.workers.drain(..) {
+            worker
+                .join()
+                .map_err(|_| io::Error::other("gzip worker panicked"))?;
+        }
+
+        let last_block = deflate_block(&self.buffer, self.level, true)?;
+        self.sink.write_all(&last_block)?;
+        self.sink.write_all(&self.crc.sum().to_le_bytes())?;
+        self.sink.write_all(&self.crc.amount().to_le_bytes())?;
+        self.sink.flush()
+    }
+}
+
+impl Gzip {
+    /// parallel_encoder generates a multithreaded gzip encoder.
+    fn parallel_encoder<'a>(
+        &self,
+        mut sink: Box<dyn io::Write + 'a>,
+        level: u32,
+        threads: usize,
+    ) -> Result<Box<dyn Encoder + 'a>, io::Error> {
+        let threads = if threads == 0 {
+            thread::available_parallelism()?.get()
+        } else {
+            threads
+        };
+        let block_size = self.block_size.unwrap_or(DEFAULT_GZIP_BLOCK_SIZE);
+
+        if block_size < MIN_GZIP_BLOCK_SIZE {
+            return Err(io::Error::other(format!(
+                "gzip block size below minimum ({MIN_GZIP_BLOCK_SIZE}): {block_size}"
+            )));
+        }
+
+        sink.write_all(&gzip_header(
+            level,
+            self.mtime.unwrap_or(0),
+            self.os.unwrap_or(DEFAULT_GZIP_OS),
+            self.filename.as_deref(),
+            self.comment.as_deref(),
+        ))?;
+
+        let (job_sender, job_receiver) = sync::mpsc::channel::<(usize, Vec<u8>)>();
+        let job_receiver = sync::Arc::new(sync::Mutex::new(job_receiver));
+        let (result_sender, results) = sync::mpsc::channel();
+        let mut workers = Vec::with_capacity(threads);
+
+        for _ in 0..threads {
+            let job_receiver = sync::Arc::clone(&job_receiver);
+            let result_sender = result_sender.clone();
+
+            workers.push(thread::spawn(move || {
+                loop {
+                    let job = match job_receiver.lock() {
+                        Ok(job_receiver) => job_receiver.recv(),
+                        Err(_) => return,
+                    };
+
+                    let Ok((index, block)) = job else {
+                        return;
+                    };
+
+                    if result_sender
+                        .send((index, deflate_block(&block, level, false)))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }));
+        }
+
+        Ok(Box::new(ParallelGzEncoder {
+            sink,
+            level,
+            block_size,
+            buffer: Vec::with_capacity(block_size),
+            jobs: Some(job_sender),
+            results,
+            workers,
+            completed: collections::BTreeMap::new(),
+            submitted: 0,
+            written: 0,
+            max_pending: threads * 2,
+            crc: flate2::Crc::new(),
+        }))
+    }
+}
+
+impl Codec for Gzip {
+    fn encoder<'a>(
+        &self,
+        sink: Box<dyn io::Write + 'a>,
+    ) -> Result<Box<dyn Encoder + 'a>, io::Error> {
+        let level = self.level.unwrap_or(DEFAULT_GZIP_LEVEL);
+
+        if level > 9 {
+            return Err(io::Error::other(format!(
+                "gzip compression level out of range (0 - 9): {level}"
+            )));
+        }
+
+        if self.filename.as_ref().is_some_and(|e| e.contains('\0')) {
+            return Err(io::Error::other("gzip filename contains NUL"));
+        }
+
+        if self.comment.as_ref().is_some_and(|e| e.contains('\0')) {
+            return Err(io::Error::other("gzip comment contains NUL"));
+        }
+
+        if let Some(threads) = self.threads {
+            return self.parallel_encoder(sink, level, threads);
+        }
+
+        let mut gz_builder = flate2::GzBuilder::new()
+            .mtime(self.mtime.unwrap_or(0))
+            .operating_system(self.os.unwrap_or(DEFAULT_GZIP_OS));
+
+        if let Some(filename) = &self.filename {
+            gz_builder = gz_builder.filename(filename.as_str());
+        }
+
+        if let Some(comment) = &self.comment {
+            gz_builder = gz_builder.comment(comment.as_str());
+        }
+
+        Ok(Box::new(
+            gz_builder.write(sink, flate2::Compression::new(level)),
+        ))
+    }
+}
+
+/// compress renders a payload through a codec, for testing.
+#[cfg(test)]
+fn compress(codec: &dyn Codec, payload: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut buf = Vec::new();
+    let mut encoder = codec.encoder(Box::new(&mut buf))?;
+    encoder.write_all(payload)?;
+    encoder.finish()?;
+    Ok(buf)
+}
+
+#[test]
+fn test_zstd_reproducible() -> Result<(), io::Error> {
+    let compression: Compression = toml::from_str("type = \"Zstd\"\nlevel = 19\nlong = 27")
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    assert_eq!(
+        compression,
+        Compression::Zstd {
+            level: Some(19),
+            long: Some(27),
+        }
+    );
+
+    let payload = b"chandler ".repeat(4096);
+    let output = compress(&compression, &payload)?;
+    assert_eq!(output, compress(&compression, &payload)?);
+    assert!(output.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+    Ok(())
+}
+
+#[test]
+fn test_gzip_reproducible() -> Result<(), io::Error> {
+    let payload = b"chandler\n".repeat(4);
+
+    // Pinned bytes guard against host specific gzip headers and encoder drift.
+    assert_eq!(
+        compress(&Gzip::default(), &payload)?,
+        vec![
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x4b, 0xce, 0x48, 0xcc,
+            0x4b, 0xc9, 0x49, 0x2d, 0xe2, 0x4a, 0xc6, 0xcd, 0x00, 0x00, 0x95, 0x69, 0x42, 0x49,
+            0x24, 0x00, 0x00, 0x00,
+        ]
+    );
+
+    let gzip: Gzip = toml::from_str(
+        "level = 9\nmtime = 1700000000\nfilename = \"hello-1.0.0.tar\"\ncomment = \"hi\"\nos = 3",
+    )
+    .map_err(|e| io::Error::other(e.to_string()))?;
+    let output = compress(&gzip, &payload)?;
+    assert_eq!(output, compress(&gzip, &payload)?);
+
+    let decoder = flate2::read::GzDecoder::new(output.as_slice());
+    let header = decoder
+        .header()
+        .ok_or_else(|| io::Error::other("missing gzip header"))?;
+    assert_eq!(header.mtime(), 1700000000);
+    assert_eq!(header.filename(), Some(b"hello-1.0.0.tar" as &[u8]));
+    assert_eq!(header.comment(), Some(b"hi" as &[u8]));
+    assert_eq!(header.operating_system(), 3);
+    assert_eq!(output[8], 2);
+
+    let invalid = Gzip {
+        filename: Some("a\0b".to_string()),
+        ..Gzip::default()
+    };
+    assert!(compress(&invalid, &payload).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_parallel_gzip() -> Result<(), io::Error> {
+    let payload: Vec<u8> = (0..300_000u32)
+        .flat_map(|e| (e % 251).to_le_bytes())
+        .collect();
+    let mut gzip = Gzip {
+        level: Some(9),
+        filename: Some("hello-1.0.0.tar".to_string()),
+        threads: Some(1),
+        block_size: Some(64 * 1024),
+        ..Gzip::default()
+    };
+    let serial_output = compress(&gzip, &payload)?;
+    gzip.threads = Some(4);
+    let parallel_output = compress(&gzip, &payload)?;
+    assert_eq!(serial_output, parallel_output);
+
+    let mut decoder = flate2::read::GzDecoder::new(parallel_output.as_slice());
+    let mut decompressed = Vec::new();
+    io::Read::read_to_end(&mut decoder, &mut decompressed)?;
+    assert_eq!(decompressed, payload);
+    assert_eq!(
+        decoder.header().and_then(|e| e.filename()),
+        Some(b"hello-1.0.0.tar" as &[u8])
+    );
+
+    for payload in [b"" as &[u8], &[0u8; 64 * 1024]] {
+        let output = compress(&gzip, payload)?;
+        let mut decoder = flate2::read::GzDecoder::new(output.as_slice());
+        let mut decompressed = Vec::new();
+        io::Read::read_to_end(&mut decoder, &mut decompressed)?;
+        assert_eq!(decompressed, payload);
+    }
+
+    gzip.block_size = Some(1);
+    assert!(compress(&gzip, &payload).is_err());
+    gzip.block_size = None;
+
+    gzip.filename = Some("hello\0.tar".to_string());
+    assert!(compress(&gzip, &payload).is_err());
+    gzip.filename = None;
+    gzip.comment = Some("hello\0".to_string());
+    assert!(compress(&gzip, &payload).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_compression_magic() -> Result<(), io::Error> {
+    let payload = b"chandler ".repeat(4096);
+    assert_eq!(compress(&Compression::Uncompressed, &payload)?, payload);
+    assert!(compress(&Compression::Gzip, &payload)?.starts_with(&[0x1f, 0x8b]));
+    assert!(
+        compress(&Compression::Xz { level: None }, &payload)?
+            .starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00])
+    );
+    assert!(compress(&Compression::Bzip2 { level: None }, &payload)?.starts_with(b"BZh9"));
+    assert!(compress(&Compression::Bzip2 { level: Some(0) }, &payload).is_err());
+    Ok(())
+}
+
+/// HeaderType models a tarball header type.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum FileMode {
+    /// Directory models a folder.
+    Directory,
+
+    /// File models an ordinary, non-directory file.
+    File,
+
+    /// Symlink models a symbolic link.
+    Symlink,
+
+    /// Fifo models a named pipe.
+    Fifo,
+
+    /// CharDevice models a character special device.
+    CharDevice,
+
+    /// BlockDevice models a block special device.
+    BlockDevice,
+}
+
+/// Unsupported models behaviors for special files (FIFOs, sockets, devices).
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum Unsupported {
+    /// Error aborts archival.
+    Error,
+
+    /// Skip excludes special files from archival, with a warning.
+    Skip,
+
+    /// Archive stores FIFOs and devices as special entries.
+    ///
+    /// Sockets, which tarballs cannot represent, are skipped with a warning.
+    Archive,
+}
+
+/// DEFAULT_UNSUPPORTED is Error.
+pub static DEFAULT_UNSUPPORTED: Unsupported = Unsupported::Error;
+
+/// special_file identifies FIFO and device files,
+/// along with portable device major and minor numbers.
+#[cfg(unix)]
+fn special_file(metadata: &fs::Metadata) -> Option<(FileMode, u32, u32)> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let file_type = metadata.file_type();
+    let rdev = metadata.rdev() as libc::dev_t;
+
+    #[allow(clippy::unnecessary_cast)]
+    let (major, minor) = (libc::major(rdev) as u32, libc::minor(rdev) as u32);
+
+    if file_type.is_fifo() {
+        Some((FileMode::Fifo, 0, 0))
+    } else if file_type.is_char_device() {
+        Some((FileMode::CharDevice, major, minor))
+    } else if file_type.is_block_device() {
+        Some((FileMode::BlockDevice, major, minor))
+    } else {
+        None
+    }
+}
+
+/// special_file identifies FIFO and device files.
+#[cfg(not(unix))]
+fn special_file(_: &fs::Metadata) -> Option<(FileMode, u32, u32)> {
+    None
+}
+
+/// normalize_link_target cleans symbolic link targets,
+/// resolving `.` and `..` components lexically.
+///
+/// Unlike `NormalizePath::normalize`, leading `..` components in relative targets are preserved.
+pub fn normalize_link_target(target: &path::Path) -> path::PathBuf {
+    let mut components: Vec<path::Component> = Vec::new();
+
+    for component in target.components() {
+        match component {
+            path::Component::CurDir => {}
+            path::Component::ParentDir => match components.last() {
+                Some(path::Component::Normal(_)) => {
+                    components.pop();
+                }
+                Some(path::Component::RootDir) | Some(path::Component::Prefix(_)) => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+
+    components.iter().collect()
+}
+
+#[test]
+fn test_normalize_link_target() {
+    assert_eq!(
+        normalize_link_target(path::Path::new("./libfoo.so.1")),
+        path::PathBuf::from("libfoo.so.1")
+    );
+    assert_eq!(
+        normalize_link_target(path::Path::new("../lib/./x/../libfoo.so.1")),
+        path::PathBuf::from("../lib/libfoo.so.1")
+    );
+    assert_eq!(
+        normalize_link_target(path::Path::new("../../etc")),
+        path::PathBuf::from("../../etc")
+    );
+    assert_eq!(
+        normalize_link_target(path::Path::new("/usr/../lib")),
+        path::PathBuf::from("/lib")
+    );
+    assert_eq!(
+        normalize_link_target(path::Path::new("/../lib")),
+        path::PathBuf::from("/lib")
+    );
+}
+
+/// is_external_link determines whether a symbolic link target
+/// resolves (lexically) outside of a root directory.
+///
+/// The link path is a normalized path relative to the root directory.
+/// Absolute targets are always external.
+pub fn is_external_link(link: &path::Path, target: &path::Path) -> bool {
+    if target.has_root() {
+        return true;
+    }
+
+    let mut depth = link
+        .parent()
+        .map(|e| {
+            e.components()
+                .filter(|c| matches!(c, path::Component::Normal(_)))
+                .count()
+        })
+        .unwrap_or(0) as i64;
+
+    for component in target.components() {
+        match component {
+            path::Component::ParentDir => depth -= 1,
+            path::Component::Normal(_) => depth += 1,
+            path::Component::CurDir => {}
+            _ => return true,
+        }
+
+        if depth < 0 {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[test]
+fn test_is_external_link() {
+    assert!(!is_external_link(
+        path::Path::new("lib/libfoo.so"),
+        path::Path::new("libfoo.so.1")
+    ));
+    assert!(!is_external_link(
+        path::Path::new("bin/foo"),
+        path::Path::new("../lib/foo")
+    ));
+    assert!(is_external_link(
+        path::Path::new("bin/foo"),
+        path::Path::new("../../foo")
+    ));
+    assert!(is_external_link(
+        path::Path::new("foo"),
+        path::Path::new("../x/../foo")
+    ));
+    assert!(is_external_link(
+        path::Path::new("lib/libfoo.so"),
+        path::Path::new("/usr/lib/libfoo.so")
+    ));
+}
+
+/// Condition models an archive entry state.
+///
+/// Fields with values present are intersected together (AND).
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Condition {
+    /// mode denotes an FileMode.
+    pub mode: Option<FileMode>,
+
+    /// paths matches file paths.
+    ///
+    /// Syntax is Rust [regex](https://crates.io/crates/regex).
+    pub paths: Option<String>,
+
+    /// content matches the leading bytes of regular files.
+    pub content: Option<Content>,
+}
+
+/// CONTENT_HEAD_SIZE denotes the number of leading file bytes examined by content conditions.
+pub static CONTENT_HEAD_SIZE: usize = 8;
+
+/// Content models file types recognizable from leading bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum Content {
+    /// Shebang denotes interpreter scripts (`#!`).
+    Shebang,
+
+    /// Elf denotes ELF binaries (Linux, BSD, etc.).
+    Elf,
+
+    /// MachO denotes Mach-O binaries (macOS, iOS), including universal binaries.
+    MachO,
+
+    /// Pe denotes PE / MZ binaries (Windows).
+    Pe,
+
+    /// Wasm denotes WebAssembly modules.
+    Wasm,
+
+    /// Executable denotes any of the above.
+    Executable,
+}
+
+impl Content {
+    /// is_match determines whether leading file bytes indicate this content.
+    pub fn is_match(&self, head: &[u8]) -> bool {
+        match self {
+            Content::Shebang => head.starts_with(b"#!"),
+            Content::Elf => head.starts_with(b"\x7fELF"),
+            Content::MachO => {
+                [
+                    [0xfe, 0xed, 0xfa, 0xce],
+                    [0xfe, 0xed, 0xfa, 0xcf],
+                    [0xce, 0xfa, 0xed, 0xfe],
+                    [0xcf, 0xfa, 0xed, 0xfe],
+                ]
+                .iter()
+                .any(|e| head.starts_with(e))
+                    // Universal binaries share a magic number with Java class files,
+                    // distinguished by a small architecture count.
+                    || (head.starts_with(&[0xca, 0xfe, 0xba, 0xbe])
+                        && head.len() >= 8
+                        && u32::from_be_bytes([head[4], head[5], head[6], head[7]]) < 20)
+            }
+            Content::Pe => head.starts_with(b"MZ"),
+            Content::Wasm => head.starts_with(b"\0asm"),
+            Content::Executable => [
+                Content::Shebang,
+                Content::Elf,
+                Content::MachO,
+                Content::Pe,
+                Content::Wasm,
+            ]
+            .iter()
+            .any(|e| e.is_match(head)),
+        }
+    }
+}
+
+#[test]
+fn test_content() {
+    assert!(Content::Shebang.is_match(b"#!/bin/sh\n"));
+    assert!(Content::Elf.is_match(b"\x7fELF\x02\x01\x01\x00"));
+    assert!(Content::MachO.is_match(&[0xcf, 0xfa, 0xed, 0xfe, 0x0c, 0x00, 0x00, 0x01]));
+    assert!(Content::MachO.is_match(&[0xca, 0xfe, 0xba, 0xbe, 0x00, 0x00, 0x00, 0x02]));
+    assert!(!Content::MachO.is_match(&[0xca, 0xfe, 0xba, 0xbe, 0x00, 0x00, 0x00, 0x34]));
+    assert!(Content::Pe.is_match(b"MZ\x90\x00"));
+    assert!(Content::Wasm.is_match(b"\0asm\x01\x00\x00\x00"));
+    assert!(Content::Executable.is_match(b"#!/usr/bin/env python3\n"));
+    assert!(!Content::Executable.is_match(b"hello\n"));
+    assert!(!Content::Executable.is_match(b""));
+}
+
+/// Rule applies given permissions for matching file patterns.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Rule {
+    /// when denotes a condition required to apply this rule's effects.
+    pub when: Option<Condition>,
+
+    /// mtime overrides entry modification timestamps (UNIX epoch).
+    pub mtime: Option<u64>,
+
+    /// uid denotes an effective user id.
+    pub uid: Option<u64>,
+
+    /// gid denotes an effective group id.
+    pub gid: Option<u64>,
+
+    /// username denotes an effective username.
+    pub username: Option<String>,
+
+    /// groupname denotes an effective group name.
+    pub groupname: Option<String>,
+
+    /// permissions denotes an effective chmod mask of file permissions.
+    pub permissions: Option<u32>,
+
+    /// add_permissions sets permission bits, after `permissions`.
+    pub add_permissions: Option<u32>,
+
+    /// remove_permissions clears permission bits, after `add_permissions`.
+    pub remove_permissions: Option<u32>,
+
+    /// chmod edits permissions with a symbolic expression (e.g. `u+x,go-w`),
+    /// after `remove_permissions`.
+    pub chmod: Option<String>,
+}
+
+/// DEFAULT_RULES implements common archive entry behaviors,
+/// such as marking most extensionless paths as chmod +x.
+pub static DEFAULT_RULES: sync::LazyLock<Vec<Rule>> = sync::LazyLock::new(|| {
+    vec![
+        Rule {
+            permissions: Some(0o755u32),
+            ..Default::default()
+        },
+        Rule {
+            when: Some(Condition {
+                mode: Some(FileMode::File),
+                paths: Some(DEFAULT_NONEXECUTABLE_FILE_PATHS.to_string()),
+                ..Default::default()
+            }),
+            permissions: Some(0o644u32),
+            ..Default::default()
+        },
+        Rule {
+            when: Some(Condition {
+                mode: Some(FileMode::File),
+                paths: Some(EXTENSIONED_FILE_PATHS.to_string()),
+                ..Default::default()
+            }),
+            permissions: Some(0o644u32),
+            ..Default::default()
+        },
+        Rule {
+            when: Some(Condition {
+                paths: Some(SYSTEM_V_INIT_PATHS.to_string()),
+                ..Default::default()
+            }),
+            permissions: Some(0o755u32),
+            ..Default::default()
+        },
+        Rule {
+            when: Some(Condition {
+                mode: Some(FileMode::Symlink),
+                ..Default::default()
+            }),
+            permissions: Some(0o777u32),
+            ..Default::default()
+        },
+    ]
+});
+
+/// CONTENT_RULES implements common archive entry behaviors based on file content,
+/// marking scripts and binaries as chmod +x, and other files as chmod -x.
+pub static CONTENT_RULES: sync::LazyLock<Vec<Rule>> = sync::LazyLock::new(|| {
+    vec![
+        Rule {
+            permissions: Some(0o755u32),
+            ..Default::default()
+        },
+        Rule {
+            when: Some(Condition {
+                mode: Some(FileMode::File),
+                ..Default::default()
+            }),
+            permissions: Some(0o644u32),
+            ..Default::default()
+        },
+        Rule {
+            when: Some(Condition {
+                mode: Some(FileMode::File),
+                content: Some(Content::Executable),
+                ..Default::default()
+            }),
+            permissions: Some(0o755u32),
+            ..Default::default()
+        },
+        Rule {
+            when: Some(Condition {
+                mode: Some(FileMode::Symlink),
+                ..Default::default()
+            }),
+            permissions: Some(0o777u32),
+            ..Default::default()
+        },
+    ]
+});
+
+/// DefaultRules models built in rule sets, applicable when `rules` is absent.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum DefaultRules {
+    /// Names selects DEFAULT_RULES, guessing executability from file paths.
+    Names,
+
+    /// Content selects CONTENT_RULES, detecting executability from file content.
+    Content,
+}
+
+#[test]
+fn test_content_rules() -> Result<(), io::Error> {
+    let mode = |filemode: FileMode, pth: &str, head: Option<&[u8]>| -> Result<u32, io::Error> {
+        let mut header = tar::Header::new_ustar();
+
+        for rule in CONTENT_RULES.iter() {
+            if rule.is_match_content(&filemode, pth, head)? {
+                rule.apply(&mut header)?;
+            }
+        }
+
+        header.mode()
+    };
+    assert_eq!(mode(FileMode::Directory, "bin", None)?, 0o755);
+    assert_eq!(
+        mode(FileMode::File, "bin/hello.sh", Some(b"#!/bin/sh"))?,
+        0o755
+    );
+    assert_eq!(
+        mode(FileMode::File, "bin/hello", Some(b"\x7fELF\x02"))?,
+        0o755
+    );
+    assert_eq!(mode(FileMode::File, "share/hello", Some(b"hello"))?, 0o644);
+    assert_eq!(mode(FileMode::File, "bin/hello.sh", None)?, 0o644);
+    Ok(())
+}
+
+impl Rule {
+    /// is_match determines whether a rule relates to an entry.
+    ///
+    /// Content conditions never match, lacking file content. See `is_match_content`.
+    pub fn is_match(&self, filemode: &FileMode, pth: &str) -> Result<bool, io::Error> {
+        self.is_match_content(filemode, pth, None)
+    }
+
+    /// is_match_content determines whether a rule relates to an entry,
+    /// given up to CONTENT_HEAD_SIZE leading bytes of regular file content.
+    pub fn is_match_content(
+        &self,
+        filemode: &FileMode,
+        pth: &str,
+        head: Option<&[u8]>,
+    ) -> Result<bool, io::Error> {
+        let condition_option = &self.when;
+
+        if condition_option.is_none() {
+            return Ok(true);
+        }
+
+        let condition = condition_option.as_ref().unwrap();
+
+        if let Some(when_mode) = condition.mode
+            && when_mode != *filemode
+        {
+            return Ok(false);
+        }
+
+        if let Some(when_paths) = &condition.paths {
+            let pattern =
+                fancy_regex::Regex::new(when_paths).map_err(|e| io::Error::other(e.to_string()))?;
+
+            if !pattern.is_match(pth).map_err(io::Error::other)? {
+                return Ok(false);
+            }
+        }
+
+        if let Some(content) = condition.content
+            && !head.is_some_and(|e| content.is_match(e))
+        {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// has_content_condition determines whether a rule examines file content.
+    pub fn has_content_condition(&self) -> bool {
+        self.when.as_ref().is_some_and(|e| e.content.is_some())
+    }
+
+    /// apply modifies headers.
+    pub fn apply(&self, header: &mut tar::Header) -> Result<(), io::Error> {
+        if let Some(mtime) = self.mtime {
+            header.set_mtime(mtime);
+        }
+
+        if let Some(uid) = self.uid {
+            header.set_uid(uid);
+        }
+
+        if let Some(gid) = self.gid {
+            header.set_gid(gid);
+        }
+
+        if let Some(username) = &self.username {
+            header.set_username(username)?;
+        }
+
+        if let Some(groupname) = &self.groupname {
+            header.set_groupname(groupname)?;
+        }
+
+        if let Some(permissions) = &self.permissions {
+            header.set_mode(*permissions);
+        }
+
+        if let Some(add_permissions) = self.add_permissions {
+            header.set_mode(header.mode()? | add_permissions);
+        }
+
+        if let Some(remove_permissions) = self.remove_permissions {
+            header.set_mode(header.mode()? & !remove_permissions);
+        }
+
+        if let Some(expression) = &self.chmod {
+            header.set_mode(chmod(
+                header.mode()?,
+                expression,
+                header.entry_type().is_dir(),
+            )?);
+        }
+
+        Ok(())
+    }
+}
+
+/// SPECIAL_PERMISSIONS collects the setuid, setgid, and sticky bits.
+pub static SPECIAL_PERMISSIONS: u32 = 0o7000;
+
+/// PERMISSION_BIT_NAMES describes individual permission bits.
+pub static PERMISSION_BIT_NAMES: [(u32, &str); 12] = [
+    (0o4000, "setuid"),
+    (0o2000, "setgid"),
+    (0o1000, "sticky"),
+    (0o400, "user read"),
+    (0o200, "user write"),
+    (0o100, "user execute"),
+    (0o040, "group read"),
+    (0o020, "group write"),
+    (0o010, "group execute"),
+    (0o004, "other read"),
+    (0o002, "other write"),
+    (0o001, "other execute"),
+];
+
+/// permission_bit_names describes the bits present in a mode.
+pub fn permission_bit_names(mode: u32) -> Vec<&'static str> {
+    PERMISSION_BIT_NAMES
+        .iter()
+        .filter(|(bit, _)| mode & bit != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// chmod evaluates a symbolic chmod expression (e.g. `u+x,go-w`, `a=rX`, `u-s`) against a mode.
+///
+/// Clauses may also supply absolute octal modes (e.g. `644`).
+pub fn chmod(mode: u32, expression: &str, is_dir: bool) -> Result<u32, io::Error> {
+    let invalid = || io::Error::other(format!("invalid chmod expression: {expression}"));
+    let mut mode = mode;
+
+    for clause in expression.split(',') {
+        if !clause.is_empty() && clause.chars().all(|e| e.is_digit(8)) {
+            mode = u32::from_str_radix(clause, 8).map_err(|_| invalid())?;
+            continue;
+        }
+
+        let ops_start = clause.find(['+', '-', '=']).ok_or_else(invalid)?;
+        let (who, mut ops) = clause.split_at(ops_start);
+        let mut who_mask = 0;
+
+        for c in who.chars() {
+            who_mask |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return Err(invalid()),
+            };
+        }
+
+        if who_mask == 0 {
+            who_mask = 0o7777;
+        }
+
+        while let Some(op) = ops.chars().next() {
+            let rest = &ops[1..];
+            let perms_end = rest.find(['+', '-', '=']).unwrap_or(rest.len());
+            let perms = &rest[..perms_end];
+            ops = &rest[perms_end..];
+            let mut bits = 0;
+
+            for c in perms.chars() {
+                bits |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                    'X' => 0,
+                    's' => 0o6000,
+                    't' => 0o1000,
+                    'u' => ((mode >> 6) & 0o7) * 0o111,
+                    'g' => ((mode >> 3) & 0o7) * 0o111,
+                    'o' => (mode & 0o7) * 0o111,
+                    _ => return Err(invalid()),
+                };
+            }
+
+            bits &= who_mask;
+
+            mode = match op {
+                '+' => mode | bits,
+                '-' => mode & !bits,
+                _ => (mode & !who_mask) | bits,
+            };
+        }
+    }
+
+    Ok(mode)
+}
+
+#[test]
+fn test_chmod() -> Result<(), io::Error> {
+    assert_eq!(chmod(0o644, "u+x", false)?, 0o744);
+    assert_eq!(chmod(0o777, "go-w", false)?, 0o755);
+    assert_eq!(chmod(0o644, "u+x,go-r", false)?, 0o700);
+    assert_eq!(chmod(0o4755, "u-s", false)?, 0o755);
+    assert_eq!(chmod(0o6755, "g-s", false)?, 0o4755);
+    assert_eq!(chmod(0o1777, "-t", true)?, 0o777);
+    assert_eq!(chmod(0o600, "a=rX", false)?, 0o444);
+    assert_eq!(chmod(0o700, "a=rX", false)?, 0o555);
+    assert_eq!(chmod(0o600, "a=rX", true)?, 0o555);
+    assert_eq!(chmod(0o640, "o=g", false)?, 0o644);
+    assert_eq!(chmod(0o600, "+x", false)?, 0o711);
+    assert_eq!(chmod(0o600, "u=rwx,g=rx,o=", false)?, 0o750);
+    assert_eq!(chmod(0o600, "u+x-w", false)?, 0o500);
+    assert_eq!(chmod(0o777, "644", false)?, 0o644);
+    assert!(chmod(0o644, "u+q", false).is_err());
+    assert!(chmod(0o644, "z+x", false).is_err());
+    assert!(chmod(0o644, "ux", false).is_err());
+    Ok(())
+}
+
+/// Ownership models policies for entry ownership (uid, gid, username, groupname).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum Ownership {
+    /// Anonymize assigns the same owner to every entry, by default 0/0 (root/root).
+    Anonymize {
+        /// uid denotes a user ID.
+        ///
+        /// Default: `0`.
+        uid: Option<u64>,
+
+        /// gid denotes a group ID.
+        ///
+        /// Default: `0`.
+        gid: Option<u64>,
+
+        /// username denotes a user name.
+        ///
+        /// Default: `root`.
+        username: Option<String>,
+
+        /// groupname denotes a group name.
+        ///
+        /// Default: `root`.
+        groupname: Option<String>,
+    },
+
+    /// Map translates uids and gids through tables, keyed by source ID (e.g. `"501" = 1000`),
+    /// clearing usernames and groupnames.
+    ///
+    /// Unmapped IDs pass through unchanged.
+    Map {
+        /// uids translates source user IDs to archive user IDs.
+        uids: Option<collections::BTreeMap<String, u64>>,
+
+        /// gids translates source group IDs to archive group IDs.
+        gids: Option<collections::BTreeMap<String, u64>>,
+    },
+
+    /// Preserve retains source uids and gids,
+    /// resolving missing usernames and groupnames from /etc/passwd and /etc/group.
+    Preserve,
+}
+
+/// DEFAULT_OWNERSHIP is Anonymize, as root/root.
+pub static DEFAULT_OWNERSHIP: Ownership = Ownership::Anonymize {
+    uid: None,
+    gid: None,
+    username: None,
+    groupname: None,
+};
+
+/// PASSWD_PATH denotes the UNIX user database.
+pub static PASSWD_PATH: &str = "/etc/passwd";
+
+/// GROUP_PATH denotes the UNIX group database.
+pub static GROUP_PATH: &str = "/etc/group";
+
+/// parse_id_names maps IDs to names, from /etc/passwd or /etc/group formatted text.
+pub fn parse_id_names(text: &str) -> HashMap<u64, String> {
+    let mut names = HashMap::new();
+
+    for line in text.lines().filter(|e| !e.starts_with('#')) {
+        let fields: Vec<&str> = line.split(':').collect();
+
+        if let [name, _, id, ..] = fields.as_slice()
+            && let Ok(id) = id.parse::<u64>()
+        {
+            names.entry(id).or_insert(name.to_string());
+        }
+    }
+
+    names
+}
+
+#[test]
+fn test_parse_id_names() {
+    let names = parse_id_names(
+        "# users\nroot:x:0:0:root:/root:/bin/sh\nwheel:x:10:root\ntoor:x:0:0::/root:/bin/sh\nbogus\n",
+    );
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[&0], "root");
+    assert_eq!(names[&10], "wheel");
+}
+
+/// load_id_names maps IDs to names, from an /etc/passwd or /etc/group formatted file.
+///
+/// Missing files yield no names.
+fn load_id_names(pth: &str) -> Result<HashMap<u64, String>, io::Error> {
+    match fs::read_to_string(pth) {
+        Ok(text) => Ok(parse_id_names(&text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// parse_id_map validates a table keyed by ID strings.
+fn parse_id_map(
+    table: &Option<collections::BTreeMap<String, u64>>,
+) -> Result<HashMap<u64, u64>, io::Error> {
+    let mut id_map = HashMap::new();
+
+    for (source, target) in table.iter().flatten() {
+        let id = source
+            .parse::<u64>()
+            .map_err(|_| io::Error::other(format!("invalid ownership map ID: {source}")))?;
+        id_map.insert(id, *target);
+    }
+
+    Ok(id_map)
+}
+
+/// Tarbomb models behaviors for archives lacking a single top level directory.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum Tarbomb {
+    /// Allow permits any number of top level paths.
+    Allow,
+
+    /// Warn logs a warning for multiple top level paths.
+    Warn,
+
+    /// Error aborts archival for multiple top level paths.
+    Error,
+}
+
+/// DEFAULT_TARBOMB is Warn.
+pub static DEFAULT_TARBOMB: Tarbomb = Tarbomb::Warn;
+
+/// Git enumerates source files from a git repository, rather than the filesystem.
+///
+/// Content, executable bits, and symbolic links follow git records.
+/// Directories and file ownership assume neutral values.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Git {
+    /// revision denotes a tree-ish (e.g. `HEAD`, `v1.0.0`).
+    ///
+    /// When absent, the index (staging area) supplies files.
+    pub revision: Option<String>,
+
+    /// commit_mtime applies the commit timestamp (of `revision`, else `HEAD`)
+    /// to each entry, rather than the UNIX epoch.
+    pub commit_mtime: Option<bool>,
+}
+
+/// git runs a git command in a directory, yielding standard output.
+fn git<I: IntoIterator<Item = S>, S: AsRef<ffi::OsStr>>(
+    dir: &path::Path,
+    args: I,
+) -> Result<Vec<u8>, io::Error> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git failure in {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+/// GitObject models a file record from a git tree or index.
+struct GitObject {
+    mode: u32,
+    id: String,
+    path: path::PathBuf,
+}
+
+/// list_git_objects enumerates files beneath a directory, from a tree-ish or else the index.
+///
+/// Paths are relative to the directory.
+fn list_git_objects(dir: &path::Path, revision: Option<&str>) -> Result<Vec<GitObject>, io::Error> {
+    let output = match revision {
+        Some(revision) => git(dir, ["ls-tree", "-r", "-z", revision, "--", "."])?,
+        None => git(dir, ["ls-files", "--stage", "-z", "--", "."])?,
+    };
+
+    let mut objects = Vec::new();
+
+    for record in output.split(|e| *e == 0).filter(|e| !e.is_empty()) {
+        let record = str::from_utf8(record).map_err(io::Error::other)?;
+        let (meta, pth) = record
+            .split_once('\t')
+            .ok_or_else(|| io::Error::other(format!("unable to parse git record: {record}")))?;
+        let fields: Vec<&str> = meta.split(' ').collect();
+
+        // ls-tree yields mode, type, and id, while ls-files yields mode, id, and stage.
+        let (mode, id) = match (revision, fields.as_slice()) {
+            (Some(_), [mode, _, id]) => (mode, id),
+            (None, [mode, id, "0"]) => (mode, id),
+            (None, [_, _, _]) => {
+                return Err(io::Error::other(format!("unmerged path: {pth}")));
+            }
+            _ => {
+                return Err(io::Error::other(format!(
+                    "unable to parse git record: {record}"
+                )));
+            }
+        };
+
+        objects.push(GitObject {
+            mode: u32::from_str_radix(mode, 8).map_err(io::Error::other)?,
+            id: id.to_string(),
+            path: path::PathBuf::from(pth),
+        });
+    }
+
+    Ok(objects)
+}
+
+/// read_git_blobs spools the content of several git objects, in order,
+/// yielding content references along with their lengths.
+fn read_git_blobs(dir: &path::Path, ids: &[&str]) -> Result<Vec<(Data, u64)>, io::Error> {
+    let mut spool_writer = SpoolWriter::new()?;
+    let mut child = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["cat-file", "--batch"])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+    let result = read_git_batch(
+        child.stdin.take(),
+        child.stdout.take(),
+        ids,
+        &mut spool_writer,
+    );
+
+    if result.is_err() {
+        // Reap git regardless of its progress.
+        let _ = child.kill();
+    }
+
+    let output = child.wait_with_output()?;
+    let blobs = result?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git failure in {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    spool_writer.finish()?;
+    Ok(blobs)
+}
+
+/// read_git_batch exchanges object IDs for blob contents with a `git cat-file --batch` process,
+/// streaming each blob into a spool.
+///
+/// Dropping stdin on return signals git to exit.
+fn read_git_batch(
+    stdin_option: Option<process::ChildStdin>,
+    stdout_option: Option<process::ChildStdout>,
+    ids: &[&str],
+    spool_writer: &mut SpoolWriter,
+) -> Result<Vec<(Data, u64)>, io::Error> {
+    let mut stdin = stdin_option.ok_or_else(|| io::Error::other("unable to access git stdin"))?;
+    let mut stdout = io::BufReader::new(
+        stdout_option.ok_or_else(|| io::Error::other("unable to access git stdout"))?,
+    );
+    let mut blobs = Vec::new();
+
+    for id in ids {
+        io::Write::write_all(&mut stdin, format!("{id}\n").as_bytes())?;
+        io::Write::flush(&mut stdin)?;
+
+        let mut line = String::new();
+        io::BufRead::read_line(&mut stdout, &mut line)?;
+
+        let size = match line.trim_end().split(' ').collect::<Vec<&str>>().as_slice() {
+            [_, "blob", size] => size.parse::<u64>().map_err(io::Error::other)?,
+            _ => return Err(io::Error::other(format!("unable to read git object: {id}"))),
+        };
+
+        let (data, len) = spool_writer.append(&mut io::Read::take(&mut stdout, size))?;
+
+        if len != size {
+            return Err(io::Error::other(format!("truncated git object: {id}")));
+        }
+
+        // Each blob ends with a line feed.
+        let mut terminator = [0u8; 1];
+        io::Read::read_exact(&mut stdout, &mut terminator)?;
+        blobs.push((data, len));
+    }
+
+    Ok(blobs)
+}
+
+/// git_history maps repository relative file paths
+/// to the timestamp of the last commit touching each path, as of a revision.
+fn git_history(
+    toplevel: &path::Path,
+    revision: &str,
+) -> Result<HashMap<path::PathBuf, u64>, io::Error> {
+    let output = git(
+        toplevel,
+        [
+            "log",
+            "--format=%x01%ct",
+            "--name-only",
+            "--no-renames",
+            "-z",
+            revision,
+            "--",
+        ],
+    )?;
+
+    // Commits arrive newest first, so the first timestamp for each path wins.
+    let mut commit_mtimes: HashMap<path::PathBuf, u64> = HashMap::new();
+    let mut commit_mtime = 0;
+
+    for record in output.split(|e| *e == 0) {
+        let record = String::from_utf8_lossy(record);
+
+        if let Some(timestamp) = record.strip_prefix('\x01') {
+            commit_mtime = timestamp.trim().parse::<u64>().map_err(io::Error::other)?;
+            continue;
+        }
+
+        let name = record.trim_start_matches('\n');
+
+        if !name.is_empty() {
+            commit_mtimes
+                .entry(path::PathBuf::from(name))
+                .or_insert(commit_mtime);
+        }
+    }
+
+    Ok(commit_mtimes)
+}
+
+/// SOURCE_DATE_EPOCH_VARIABLE denotes the [reproducible builds](https://reproducible-builds.org/specs/source-date-epoch/)
+/// environment variable for a UNIX epoch timestamp.
+pub static SOURCE_DATE_EPOCH_VARIABLE: &str = "SOURCE_DATE_EPOCH";
+
+/// source_date_epoch queries the SOURCE_DATE_EPOCH environment variable.
+pub fn source_date_epoch() -> Result<Option<u64>, io::Error> {
+    match env::var(SOURCE_DATE_EPOCH_VARIABLE) {
+        Ok(epoch) if !epoch.is_empty() => epoch.parse::<u64>().map(Some).map_err(|_| {
+            io::Error::other(format!("invalid {SOURCE_DATE_EPOCH_VARIABLE}: {epoch}"))
+        }),
+        _ => Ok(None),
+    }
+}
+
+/// Mtime models policies for entry modification times.
+///
+/// Policies without an explicit epoch fall back to SOURCE_DATE_EPOCH.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum Mtime {
+    /// Preserve retains source modification times.
+    Preserve,
+
+    /// Replace assigns the epoch to every entry.
+    Replace {
+        /// epoch denotes a UNIX timestamp.
+        ///
+        /// Default: `SOURCE_DATE_EPOCH`.
+        epoch: Option<u64>,
+    },
+
+    /// Clamp limits modification times newer than the epoch to the epoch.
+    Clamp {
+        /// epoch denotes a UNIX timestamp.
+        ///
+        /// Default: `SOURCE_DATE_EPOCH`.
+        epoch: Option<u64>,
+    },
+
+    /// Git assigns each file the timestamp of the last commit touching its path,
+    /// and each directory the latest timestamp among its contents.
+    ///
+    /// Files lacking git history retain source modification times.
+    Git,
+}
+
+/// SkipMatch models the subject of `skip_paths` patterns.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum SkipMatch {
+    /// Relative matches paths relative to each source root (e.g. `build/hello.o`).
+    Relative,
+
+    /// Absolute matches canonical, absolute paths (e.g. `/home/user/hello/build/hello.o`).
+    Absolute,
+}
+
+/// DEFAULT_SKIP_MATCH is Relative.
+pub static DEFAULT_SKIP_MATCH: SkipMatch = SkipMatch::Relative;
+
+/// Mapping rewrites source paths into archive paths, like `tar --transform`.
+///
+/// Exactly one of `src` or `pattern` is expected.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Mapping {
+    /// src matches a source path exactly, or as a parent directory.
+    ///
+    /// Descendants of a matching directory retain their relative paths beneath `dest`.
+    pub src: Option<String>,
+
+    /// pattern matches source paths.
+    ///
+    /// Syntax is Rust [regex](https://crates.io/crates/regex).
+    pub pattern: Option<String>,
+
+    /// dest denotes the replacement archive path.
+    ///
+    /// With `pattern`, replaces the first match, expanding capture groups (`$1`, `${name}`).
+    pub dest: String,
+}
+
+impl Mapping {
+    /// map rewrites a normalized source path, when matching.
+    pub fn map(&self, pth: &str) -> Result<Option<String>, io::Error> {
+        if let Some(src) = &self.src {
+            let src = src.trim_end_matches('/');
+
+            if pth == src {
+                return Ok(Some(self.dest.clone()));
+            }
+
+            if let Some(descendant) = pth.strip_prefix(src).and_then(|e| e.strip_prefix('/')) {
+                return Ok(Some(
+                    path::Path::new(&self.dest)
+                        .join(descendant)
+                        .to_string_lossy()
+                        .to_string(),
+                ));
+            }
+
+            return Ok(None);
+        }
+
+        if let Some(pattern) = &self.pattern {
+            let pattern =
+                fancy_regex::Regex::new(pattern).map_err(|e| io::Error::other(e.to_string()))?;
+
+            if !pattern.is_match(pth).map_err(io::Error::other)? {
+                return Ok(None);
+            }
+
+            return Ok(Some(
+                pattern
+                    .try_replacen(pth, 1, self.dest.as_str())
+                    .map_err(io::Error::other)?
+                    .to_string(),
+            ));
+        }
+
+        Err(io::Error::other(format!(
+            "mapping requires src or pattern: {:?}",
+            self
+        )))
+    }
+}
+
+#[test]
+fn test_mapping() -> Result<(), io::Error> {
+    let file_mapping = Mapping {
+        src: Some("target/release/foo".to_string()),
+        pattern: None,
+        dest: "foo-1.2.3/bin/foo".to_string(),
+    };
+    assert_eq!(
+        file_mapping.map("target/release/foo")?,
+        Some("foo-1.2.3/bin/foo".to_string())
+    );
+    assert_eq!(file_mapping.map("target/release/foobar")?, None);
+
+    let directory_mapping = Mapping {
+        src: Some("docs/".to_string()),
+        pattern: None,
+        dest: "foo-1.2.3/share/doc".to_string(),
+    };
+    assert_eq!(
+        directory_mapping.map("docs/guide/README.md")?,
+        Some("foo-1.2.3/share/doc/guide/README.md".to_string())
+    );
+
+    let pattern_mapping = Mapping {
+        src: None,
+        pattern: Some(r"^target/(?P<profile>[^/]+)/(.+)$".to_string()),
+        dest: "foo-1.2.3/${profile}/$2".to_string(),
+    };
+    assert_eq!(
+        pattern_mapping.map("target/release/foo")?,
+        Some("foo-1.2.3/release/foo".to_string())
+    );
+    assert_eq!(pattern_mapping.map("src/lib.rs")?, None);
+
+    // Destinations escaping the archive root present errors.
+    for dest in ["/etc/evil", "../evil2", "foo/../../evil3"] {
+        let chandler = Chandler {
+            mappings: Some(vec![Mapping {
+                src: Some("hello".to_string()),
+                pattern: None,
+                dest: dest.to_string(),
+            }]),
+            ..Chandler::default()
+        };
+        let mut entry = test_entry("hello", FileMode::File);
+        let err = chandler.map(&mut entry).unwrap_err();
+        assert!(err.to_string().contains(dest));
+    }
+
+    Ok(())
+}
+
+/// Chandler assembles tarballs (TGZ, TAR.GZ, TAR.ZST, TAR.XZ, TAR.BZ2, TAR).
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Chandler {
+    /// verbose enables additional logging.
+    pub verbose: Option<bool>,
+
+    /// header denotes a tape archive format.
+    pub header: Option<HeaderType>,
+
+    /// compression denotes a tarball compression format.
+    pub compression: Option<Compression>,
+
+    /// gzip customizes gzip compression.
+    pub gzip: Option<Gzip>,
+
+    /// codec supplies a custom compression format, taking precedence over `compression`.
+    #[serde(skip)]
+    pub codec: Option<Box<dyn Codec>>,
+
+    /// cwd customizes the current working directory.
+    pub cwd: Option<path::PathBuf>,
+
+    /// deduplicate stores regular files with identical content as hard links,
+    /// in addition to files sharing an inode.
+    ///
+    /// Linked entries share the metadata of the first such entry, upon extraction.
+    pub deduplicate: Option<bool>,
+
+    /// forbid_external_symlinks rejects symbolic links
+    /// with absolute targets or relative targets outside of the source root.
+    pub forbid_external_symlinks: Option<bool>,
+
+    /// unsupported denotes a behavior for special files (FIFOs, sockets, devices).
+    pub unsupported: Option<Unsupported>,
+
+    /// skip_paths collects file path patterns to exclude from archival,
+    ///
+    /// Syntax is Rust [regex](https://crates.io/crates/regex).
+    pub skip_paths: Option<Vec<String>>,
+
+    /// skip_match denotes the subject of `skip_paths` patterns.
+    pub skip_match: Option<SkipMatch>,
+
+    /// include_paths collects file path patterns to retain for archival,
+    /// matched against the same paths as `skip_paths` (see `skip_match`).
+    ///
+    /// When nonempty, only entries matching at least one pattern (and no `skip_paths`)
+    /// enter the archive, along with their ancestor directories.
+    ///
+    /// Syntax is Rust [regex](https://crates.io/crates/regex).
+    pub include_paths: Option<Vec<String>>,
+
+    /// ignore_files collects the names of gitignore style files,
+    /// consulted in each directory of a source tree (e.g. `.gitignore`, `.chandlerignore`).
+    ///
+    /// Patterns apply alongside `skip_paths`. Later files take precedence.
+    /// Ignore files in directories above a source root, such as the `.gitignore`
+    /// of an enclosing repository, are not consulted.
+    pub ignore_files: Option<Vec<String>>,
+
+    /// mappings collects a sequence of source path rewrites,
+    /// applied before rules.
+    ///
+    /// The first matching mapping applies.
+    pub mappings: Option<Vec<Mapping>>,
+
+    /// prefix nests all entries beneath a top level directory (e.g. `hello-1.0.0`),
+    /// applied after mappings, rules, and permission limits.
+    pub prefix: Option<String>,
+
+    /// tarbomb denotes a behavior for archives lacking a single top level directory.
+    pub tarbomb: Option<Tarbomb>,
+
+    /// git enumerates directory sources from git records, like `git archive`.
+    pub git: Option<Git>,
+
+    /// max_permissions denotes a ceiling for entry permissions (e.g. `0o755`),
+    /// applied after rules. Symbolic links are exempt.
+    pub max_permissions: Option<u32>,
+
+    /// special_paths collects file path patterns permitted to retain
+    /// setuid, setgid, and sticky bits, even beyond `max_permissions`.
+    /// Other entries lose these bits.
+    ///
+    /// Syntax is Rust [regex](https://crates.io/crates/regex).
+    pub special_paths: Option<Vec<String>>,
+
+    /// ownership denotes a policy for entry ownership, applied before rules.
+    pub ownership: Option<Ownership>,
+
+    /// mtime denotes a policy for entry modification times, applied before rules.
+    ///
+    /// Defaults to Clamp when SOURCE_DATE_EPOCH is set, else Preserve.
+    pub mtime: Option<Mtime>,
+
+    /// default_rules selects a built in rule set, when `rules` is absent.
+    pub default_rules: Option<DefaultRules>,
+
+    /// rules collects a sequence of rules to apply to inbound files.
+    pub rules: Option<Vec<Rule>>,
+}
+
+/// permissions_to_u32 converts fs::Permissions objects to chmod integers.
+pub fn permissions_to_u32(permissions: fs::Permissions) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.mode()
+    }
+    #[cfg(windows)]
+    {
+        if permissions.readonly() {
+            0o444u32
+        } else {
+            0o666u32
+        }
+    }
+}
+
+/// Data sources archive entry content.
+enum Data {
+    /// Empty models entries without content, such as directories.
+    Empty,
+
+    /// File reads content from a file path.
+    File(path::PathBuf),
+
+    /// Spool reads content from a region (offset, length) of a spool file.
+    Spool(sync::Arc<Spool>, u64, u64),
+}
+
+/// SPOOL_COUNTER distinguishes spool files within a process.
+static SPOOL_COUNTER: sync::atomic::AtomicUsize = sync::atomic::AtomicUsize::new(0);
+
+/// Spool models a temporary file holding entry content, such as members of a source tarball,
+/// so that large archives need not fit in memory.
+///
+/// The file is removed once dropped.
+struct Spool {
+    /// path denotes the temporary file.
+    path: path::PathBuf,
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// SpoolWriter appends content to a new spool.
+struct SpoolWriter {
+    /// spool denotes the destination.
+    spool: sync::Arc<Spool>,
+
+    /// writer buffers spool output.
+    writer: io::BufWriter<fs::File>,
+
+    /// offset denotes the current spool length.
+    offset: u64,
+}
+
+impl SpoolWriter {
+    /// new creates a spool in the temporary directory.
+    fn new() -> Result<Self, io::Error> {
+        let path = env::temp_dir().join(format!(
+            "chandler-spool-{}-{}",
+            process::id(),
+            SPOOL_COUNTER.fetch_add(1, sync::atomic::Ordering::Relaxed)
+        ));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let file = options.open(&path)?;
+        Ok(SpoolWriter {
+            spool: sync::Arc::new(Spool { path }),
+            writer: io::BufWriter::new(file),
+            offset: 0,
+        })
+    }
+
+    /// append copies content into the spool,
+    /// yielding a reference to the content, along with its length.
+    fn append<R: io::Read>(&mut self, reader: &mut R) -> Result<(Data, u64), io::Error> {
+        let len = io::copy(reader, &mut self.writer)?;
+        let data = Data::Spool(sync::Arc::clone(&self.spool), self.offset, len);
+        self.offset += len;
+        Ok((data, len))
+    }
+
+    /// finish flushes the spool, so that spooled content becomes readable.
+    fn finish(mut self) -> Result<(), io::Error> {
+        io::Write::flush(&mut self.writer)
+    }
+}
+
+/// LinkKey identifies file content shared by hard links.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum LinkKey {
+    /// Inode identifies a file system inode, by device and inode number.
+    Inode(u64, u64),
+
+    /// Path identifies a regular file entry within a source tarball.
+    Path(path::PathBuf),
+}
+
+/// Entry models a pending archive entry.
+struct Entry {
+    /// path denotes a normalized archive path.
+    path: path::PathBuf,
+
+    /// filemode denotes the entry type.
+    filemode: FileMode,
+
+    /// header carries preliminary entry metadata.
+    header: tar::Header,
+
+    /// data sources entry content.
+    data: Data,
+
+    /// link_key identifies content shared with other hard links.
+    link_key: Option<LinkKey>,
+}
+
+/// test_entry generates a contentless entry with a blank header, for testing.
+#[cfg(test)]
+fn test_entry<P: AsRef<path::Path>>(pth: P, filemode: FileMode) -> Entry {
+    Entry {
+        path: pth.as_ref().to_path_buf(),
+        filemode,
+        header: tar::Header::new_ustar(),
+        data: Data::Empty,
+        link_key: None,
+    }
+}
+
+/// TestDir manages a scratch directory, removed on drop, for testing.
+#[cfg(test)]
+struct TestDir {
+    /// path denotes the scratch directory.
+    path: path::PathBuf,
+}
+
+#[cfg(test)]
+impl TestDir {
+    /// new creates a scratch directory in the temporary directory.
+    fn new(name: &str) -> Result<Self, io::Error> {
+        Self::new_in(&env::temp_dir(), name)
+    }
+
+    /// new_in creates a scratch directory within the given parent directory.
+    fn new_in(parent: &path::Path, name: &str) -> Result<Self, io::Error> {
+        let path = parent.join(format!("chandler-test-{}-{}", name, process::id()));
+        fs::create_dir_all(&path)?;
+        Ok(TestDir { path })
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = path::Path;
+
+    fn deref(&self) -> &path::Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl AsRef<path::Path> for TestDir {
+    fn as_ref(&self) -> &path::Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// test_tarball writes an uncompressed tarball, for testing.
+///
+/// Each member supplies a header template presetting its format, entry type, and mode.
+#[cfg(test)]
+fn test_tarball(
+    pth: &path::Path,
+    members: &[(&tar::Header, &str, &[u8])],
+) -> Result<(), io::Error> {
+    let mut builder = tar::Builder::new(fs::File::create(pth)?);
+
+    for (template, member_path, content) in members {
+        let mut header = (*template).clone();
+        header.set_path(member_path)?;
+        header.set_size(content.len() as u64);
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_cksum();
+        builder.append(&header, *content)?;
+    }
+
+    builder.into_inner()?;
+    Ok(())
+}
+
+impl Data {
+    /// open streams content.
+    fn open(&self) -> Result<Box<dyn io::Read + '_>, io::Error> {
+        match self {
+            Data::Empty => Ok(Box::new(io::empty())),
+            Data::File(pth) => Ok(Box::new(fs::File::open(pth)?)),
+            Data::Spool(spool, offset, len) => {
+                let mut file = fs::File::open(&spool.path)?;
+                io::Seek::seek(&mut file, io::SeekFrom::Start(*offset))?;
+                Ok(Box::new(io::Read::take(file, *len)))
+            }
+        }
+    }
+
+    /// digest hashes content, for deduplication.
+    fn digest(&self) -> Result<u64, io::Error> {
+        let mut hasher = hash::DefaultHasher::new();
+        let mut reader = io::BufReader::new(self.open()?);
+
+        loop {
+            let buf = io::BufRead::fill_buf(&mut reader)?;
+
+            if buf.is_empty() {
+                break;
+            }
+
+            hasher.write(buf);
+            let n = buf.len();
+            io::BufRead::consume(&mut reader, n);
+        }
+
+        Ok(hasher.finish())
+    }
+
+    /// read loads content into memory.
+    fn read(&self) -> Result<Vec<u8>, io::Error> {
+        let mut buf = Vec::new();
+        io::Read::read_to_end(&mut self.open()?, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// head loads up to CONTENT_HEAD_SIZE leading bytes of content.
+    fn head(&self) -> Result<Vec<u8>, io::Error> {
+        let mut buf = Vec::new();
+        io::Read::read_to_end(
+            &mut io::Read::take(self.open()?, CONTENT_HEAD_SIZE as u64),
+            &mut buf,
+        )?;
+        Ok(buf)
+    }
+}
+
+/// resolve_hard_links selects hard link targets for sorted entries.
+///
+/// The first entry (in archive order) sharing a link key becomes the link target,
+/// so that link targets remain deterministic.
+/// When deduplicating, regular files with identical content link together as well.
+fn resolve_hard_links(
+    entries: &[Entry],
+    deduplicate: bool,
+) -> Result<Vec<Option<path::PathBuf>>, io::Error> {
+    let mut links = Vec::with_capacity(entries.len());
+    let mut link_targets: HashMap<&LinkKey, &path::Path> = HashMap::new();
+    let mut content_targets: HashMap<(u64, u64), Vec<&Entry>> = HashMap::new();
+
+    for entry in entries {
+        if entry.filemode != FileMode::File {
+            links.push(None);
+            continue;
+        }
+
+        if let Some(link_key) = &entry.link_key {
+            if let Some(target) = link_targets.get(link_key) {
+                links.push(Some(target.to_path_buf()));
+                continue;
+            }
+
+            link_targets.insert(link_key, &entry.path);
+        }
+
+        if deduplicate {
+            let key = (entry.header.size()?, entry.data.digest()?);
+            let candidates = content_targets.entry(key).or_default();
+            let mut target_option = None;
+
+            if !candidates.is_empty() {
+                let content = entry.data.read()?;
+
+                for candidate in candidates.iter() {
+                    if candidate.data.read()? == content {
+                        target_option = Some(candidate.path.clone());
+                        break;
+                    }
+                }
+            }
+
+            if target_option.is_none() {
+                candidates.push(entry);
+            }
+
+            links.push(target_option);
+            continue;
+        }
+
+        links.push(None);
+    }
+
+    Ok(links)
+}
+
+#[test]
+fn test_resolve_hard_links() -> Result<(), io::Error> {
+    let mut spool_writer = SpoolWriter::new()?;
+    let mut entry = |pth: &str, content: &[u8], link_key: Option<LinkKey>| {
+        let mut entry = test_entry(pth, FileMode::File);
+        let (data, len) = spool_writer.append(&mut &content[..])?;
+        entry.header.set_size(len);
+        entry.data = data;
+        entry.link_key = link_key;
+        Ok::<Entry, io::Error>(entry)
+    };
+    let entries = vec![
+        entry("a", b"hello", Some(LinkKey::Inode(1, 2)))?,
+        entry("b", b"hello", None)?,
+        entry("c", b"world", Some(LinkKey::Inode(1, 3)))?,
+        entry("d", b"world", Some(LinkKey::Inode(1, 3)))?,
+    ];
+    spool_writer.finish()?;
+
+    assert_eq!(
+        resolve_hard_links(&entries, false)?,
+        vec![None, None, None, Some(path::PathBuf::from("c"))]
+    );
+    assert_eq!(
+        resolve_hard_links(&entries, true)?,
+        vec![
+            None,
+            Some(path::PathBuf::from("a")),
+            None,
+            Some(path::PathBuf::from("c")),
+        ]
+    );
+    Ok(())
+}
+
+/// ZSTD_WINDOW_LOG_MAX permits decompressing long distance zstd streams.
+static ZSTD_WINDOW_LOG_MAX: u32 = 31;
+
+/// read_manifest parses a list of paths, delimited by newlines or else NUL bytes.
+///
+/// Blank entries are ignored.
+pub fn read_manifest<R: io::Read>(
+    mut reader: R,
+    null: bool,
+) -> Result<Vec<path::PathBuf>, io::Error> {
+    let mut buf = String::new();
+    reader.read_to_string(&mut buf)?;
+
+    let delimiter = if null { '\0' } else { '\n' };
+
+    Ok(buf
+        .split(delimiter)
+        .map(|e| if null { e } else { e.trim_end_matches('\r') })
+        .filter(|e| !e.is_empty())
+        .map(path::PathBuf::from)
+        .collect())
+}
+
+#[test]
+fn test_read_manifest() -> Result<(), io::Error> {
+    assert_eq!(
+        read_manifest(&b"bin/hello\r\n\nREADME.md\n"[..], false)?,
+        vec![
+            path::PathBuf::from("bin/hello"),
+            path::PathBuf::from("README.md")
+        ]
+    );
+    assert_eq!(
+        read_manifest(&b"bin/hello world\n\0README.md\0"[..], true)?,
+        vec![
+            path::PathBuf::from("bin/hello world\n"),
+            path::PathBuf::from("README.md")
+        ]
+    );
+    Ok(())
+}
+
+/// open_archive reads tarballs (TGZ, TAR.GZ, TAR.ZST, TAR.XZ, TAR.BZ2, TAR),
+/// transparently decompressing streams.
+pub fn open_archive(source: &path::Path) -> Result<tar::Archive<Box<dyn io::Read>>, io::Error> {
+    let mut reader = io::BufReader::new(fs::File::open(source)?);
+    let magic = io::BufRead::fill_buf(&mut reader)?;
+    let is_gzip = magic.starts_with(&[0x1f, 0x8b]);
+    let is_zstd = magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]);
+    let is_xz = magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]);
+    let is_bzip2 = magic.starts_with(b"BZh");
+
+    let decoder: Box<dyn io::Read> = if is_gzip {
+        Box::new(flate2::read::MultiGzDecoder::new(reader))
+    } else if is_zstd {
+        let mut zstd_decoder = zstd::Decoder::with_buffer(reader)?;
+        zstd_decoder.window_log_max(ZSTD_WINDOW_LOG_MAX)?;
+        Box::new(zstd_decoder)
+    } else if is_xz {
+        Box::new(liblzma::bufread::XzDecoder::new_multi_decoder(reader))
+    } else if is_bzip2 {
+        Box::new(bzip2::bufread::MultiBzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    Ok(tar::Archive::new(decoder))
+}
+
+impl Chandler {
     /// load generates a Chandler.
     pub fn load() -> Result<Self, io::Error> {
         let pth = CONFIGURATION_FILENAME;
@@ -360,121 +2896,2501 @@ impl Chandler {
         Ok(chandler)
     }
 
-    /// archive generates a tarball.
-    pub fn archive(&self, target: &path::Path, source: &path::Path) -> Result<(), io::Error> {
-        let skip_path_pattern: fancy_regex::Regex =
-            generate_skip_path_pattern(self.skip_paths.as_ref().unwrap_or(&DEFAULT_SKIP_PATHS))
-                .map_err(io::Error::other)?;
-        let rules: &Vec<Rule> = self.rules.as_ref().unwrap_or(&DEFAULT_RULES);
+    /// new_header generates a blank header in the configured tar format.
+    fn new_header(&self) -> tar::Header {
+        match self.header {
+            Some(HeaderType::Gnu) => tar::Header::new_gnu(),
+            Some(HeaderType::TarV7) => tar::Header::new_old(),
+            _ => tar::Header::new_ustar(),
+        }
+    }
+
+    /// is_skip determines whether a path is excluded from archival.
+    fn is_skip(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        pth: &str,
+        pth_clean_str: &str,
+    ) -> Result<bool, io::Error> {
+        if !skip_path_pattern
+            .is_match(pth)
+            .map_err(|e| io::Error::other(e.to_string()))?
+        {
+            return Ok(false);
+        }
+
+        if let Some(true) = self.verbose {
+            eprintln!("skipping {pth_clean_str}");
+        }
+
+        Ok(true)
+    }
+
+    /// set_symlink configures a symbolic link header,
+    /// given a link path relative to the source root.
+    fn set_symlink(
+        &self,
+        header: &mut tar::Header,
+        pth_clean_str: &str,
+        link: &path::Path,
+        target: &path::Path,
+    ) -> Result<(), io::Error> {
+        let target_clean = normalize_link_target(target);
+
+        if let Some(true) = self.forbid_external_symlinks
+            && is_external_link(link, &target_clean)
+        {
+            return Err(io::Error::other(format!(
+                "symlink escapes source root: {pth_clean_str} -> {}",
+                target_clean.display()
+            )));
+        }
+
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_link_name(&target_clean)?;
+        header.set_size(0);
+        Ok(())
+    }
+
+    /// set_special configures a FIFO or device header.
+    fn set_special(
+        &self,
+        header: &mut tar::Header,
+        filemode: FileMode,
+        major: u32,
+        minor: u32,
+    ) -> Result<(), io::Error> {
+        header.set_entry_type(match filemode {
+            FileMode::CharDevice => tar::EntryType::Char,
+            FileMode::BlockDevice => tar::EntryType::Block,
+            _ => tar::EntryType::Fifo,
+        });
+        header.set_size(0);
+        header.set_device_major(major)?;
+        header.set_device_minor(minor)
+    }
+
+    /// unsupported rejects or skips special files lacking archival support.
+    fn unsupported(&self, pth_clean_str: &str) -> Result<(), io::Error> {
+        if self.unsupported.unwrap_or(DEFAULT_UNSUPPORTED) == Unsupported::Error {
+            return Err(io::Error::other(format!(
+                "unsupported file type: {pth_clean_str}"
+            )));
+        }
+
+        eprintln!("warning: skipping unsupported file type: {pth_clean_str}");
+        Ok(())
+    }
+
+    /// walk collects archive entries from a directory tree.
+    fn walk(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        source: &path::Path,
+    ) -> Result<Vec<Entry>, io::Error> {
+        let mut entries = Vec::new();
+        let walker = walkdir::WalkDir::new(source).sort_by(
+            |a: &walkdir::DirEntry, b: &walkdir::DirEntry| a.file_name().cmp(b.file_name()),
+        );
+
+        let ignore_files = self.ignore_files.as_ref().unwrap_or(&DEFAULT_IGNORE_FILES);
+        let mut ignores: Vec<(path::PathBuf, Vec<IgnorePattern>)> = Vec::new();
+        let mut skip_error = None;
+
+        // Skipped directories exclude their contents, without visiting them.
+        let mut it = walker.into_iter().filter_entry(|e| {
+            match self.is_skip_file(
+                skip_path_pattern,
+                source,
+                e.path(),
+                e.path_is_symlink(),
+                e.file_type().is_dir(),
+            ) {
+                Ok(skip) => !skip,
+                Err(err) => {
+                    skip_error.get_or_insert(err);
+                    true
+                }
+            }
+        });
+
+        while let Some(entry) = it.next() {
+            let entry = entry?;
+            let pth = entry.path();
+            let is_dir = entry.file_type().is_dir();
+
+            while let Some((dir, _)) = ignores.last()
+                && !(pth.starts_with(dir) && pth != dir)
+            {
+                ignores.pop();
+            }
+
+            if is_ignored(&ignores, pth, is_dir)? {
+                if let Some(true) = self.verbose {
+                    eprintln!("skipping {}", pth.normalize().display());
+                }
+
+                // Ignored directories exclude their contents, as with git.
+                if is_dir {
+                    it.skip_current_dir();
+                }
+
+                continue;
+            }
+
+            if is_dir {
+                let mut patterns = Vec::new();
+
+                for ignore_file in ignore_files {
+                    let ignore_path = pth.join(ignore_file);
+
+                    if ignore_path.is_file() {
+                        patterns.extend(load_ignore_file(&ignore_path)?);
+                    }
+                }
+
+                if !patterns.is_empty() {
+                    ignores.push((pth.to_path_buf(), patterns));
+                }
+            }
+
+            if let Some(entry) = self.stat(source, pth, entry.metadata()?)? {
+                entries.push(entry);
+            }
+        }
+
+        drop(it);
+
+        if let Some(err) = skip_error {
+            return Err(err);
+        }
+
+        Ok(entries)
+    }
+
+    /// is_skip_file determines whether a filesystem path is excluded from archival.
+    fn is_skip_file(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        source: &path::Path,
+        pth: &path::Path,
+        is_symlink: bool,
+        is_dir: bool,
+    ) -> Result<bool, io::Error> {
+        let pth_clean = pth.normalize();
+        let pth_clean_str = pth_clean
+            .to_str()
+            .ok_or_else(|| io::Error::other(format!("unable to render path {:?}", pth_clean)))?;
+
+        if self.skip_match.unwrap_or(DEFAULT_SKIP_MATCH) == SkipMatch::Relative {
+            let source_clean = source.normalize();
+            let mut relative = pth_clean.strip_prefix(&source_clean).unwrap_or(&pth_clean);
+
+            // File sources match by name, relative to their parent directory.
+            if relative.as_os_str().is_empty()
+                && !is_dir
+                && let Some(name) = pth_clean.file_name()
+            {
+                relative = path::Path::new(name);
+            }
+
+            return self.is_skip_relative(
+                skip_path_pattern,
+                path::Path::new(""),
+                relative,
+                is_dir,
+                pth_clean_str,
+            );
+        }
+
+        // Avoid following symbolic links, which may dangle.
+        let pth_abs = if is_symlink {
+            match (pth.parent(), pth.file_name()) {
+                (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+                    parent.canonicalize()?.join(name)
+                }
+                (_, Some(name)) => env::current_dir()?.join(name),
+                _ => pth.canonicalize()?,
+            }
+        } else {
+            pth.canonicalize()?
+        };
+        let pth_abs_str = pth_abs.to_str().ok_or(io::Error::other(format!(
+            "unable to process path: {}",
+            pth_abs.display()
+        )))?;
+
+        let source_abs = if source.as_os_str().is_empty() {
+            env::current_dir()?
+        } else {
+            source.canonicalize()?
+        };
+
+        if let Ok(relative) = pth_abs.strip_prefix(&source_abs)
+            && !relative.as_os_str().is_empty()
+        {
+            return self.is_skip_relative(
+                skip_path_pattern,
+                &source_abs,
+                relative,
+                is_dir,
+                pth_clean_str,
+            );
+        }
+
+        Ok(self.is_skip(skip_path_pattern, pth_abs_str, pth_clean_str)?
+            || (is_dir
+                && self.is_skip(skip_path_pattern, &format!("{pth_abs_str}/"), pth_clean_str)?))
+    }
+
+    /// is_skip_relative determines whether a path relative to a source root
+    /// is excluded from archival, joining match subjects onto a base path.
+    ///
+    /// Directories also match with a trailing slash (e.g. `^build/`).
+    /// Paths within skipped directories are likewise excluded,
+    /// for relative paths.
+    /// Source roots (empty relative paths) are never skipped.
+    fn is_skip_relative(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        base: &path::Path,
+        relative: &path::Path,
+        is_dir: bool,
+        pth_clean_str: &str,
+    ) -> Result<bool, io::Error> {
+        let subject = |pth: &path::Path| -> Result<String, io::Error> {
+            let subject = base.join(pth);
+            subject
+                .to_str()
+                .map(|e| e.to_string())
+                .ok_or_else(|| io::Error::other(format!("unable to render path {:?}", subject)))
+        };
+
+        if relative.as_os_str().is_empty() {
+            return Ok(false);
+        }
+
+        // Absolute paths outside of any source root match only themselves.
+        if !relative.has_root() {
+            for ancestor in relative.ancestors().skip(1) {
+                if ancestor.as_os_str().is_empty() {
+                    continue;
+                }
+
+                let ancestor_subject = subject(ancestor)?;
+
+                if self.is_skip(skip_path_pattern, &ancestor_subject, pth_clean_str)?
+                    || self.is_skip(
+                        skip_path_pattern,
+                        &format!("{ancestor_subject}/"),
+                        pth_clean_str,
+                    )?
+                {
+                    return Ok(true);
+                }
+            }
+        }
+
+        let relative_subject = subject(relative)?;
+        Ok(
+            self.is_skip(skip_path_pattern, &relative_subject, pth_clean_str)?
+                || (is_dir
+                    && self.is_skip(
+                        skip_path_pattern,
+                        &format!("{relative_subject}/"),
+                        pth_clean_str,
+                    )?),
+        )
+    }
+
+    /// is_skip_tracked determines whether a git tracked path is excluded from archival,
+    /// given a path relative to the source root.
+    fn is_skip_tracked(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        source: &path::Path,
+        source_abs: &path::Path,
+        relative: &path::Path,
+        is_dir: bool,
+    ) -> Result<bool, io::Error> {
+        let base = match self.skip_match.unwrap_or(DEFAULT_SKIP_MATCH) {
+            SkipMatch::Relative => path::Path::new(""),
+            SkipMatch::Absolute => source_abs,
+        };
+        let pth_clean = source.join(relative).normalize();
+        let pth_clean_str = pth_clean
+            .to_str()
+            .ok_or_else(|| io::Error::other(format!("unable to render path {:?}", pth_clean)))?;
+        self.is_skip_relative(skip_path_pattern, base, relative, is_dir, pth_clean_str)
+    }
+
+    /// list collects an archive entry for a single path, without recursing into directories.
+    fn list(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        source: &path::Path,
+    ) -> Result<Vec<Entry>, io::Error> {
+        let metadata = fs::symlink_metadata(source)?;
+
+        let root = path::Path::new("");
+
+        if self.is_skip_file(
+            skip_path_pattern,
+            root,
+            source,
+            metadata.file_type().is_symlink(),
+            metadata.is_dir(),
+        )? {
+            return Ok(Vec::new());
+        }
+
+        Ok(self.stat(root, source, metadata)?.into_iter().collect())
+    }
+
+    /// stat generates an archive entry from file metadata,
+    /// given a path beneath a source root.
+    ///
+    /// Skipped unsupported file types yield None.
+    fn stat(
+        &self,
+        source: &path::Path,
+        pth: &path::Path,
+        metadata: fs::Metadata,
+    ) -> Result<Option<Entry>, io::Error> {
+        let pth_clean = pth.normalize();
+        let pth_clean_str = pth_clean
+            .to_str()
+            .ok_or_else(|| io::Error::other(format!("unable to render path {:?}", pth_clean)))?;
+
+        if pth_clean_str.is_empty() || pth_clean_str == "." {
+            return Ok(None);
+        }
+
+        let mut header = self.new_header();
+
+        let mtime = metadata
+            .modified()?
+            .duration_since(time::UNIX_EPOCH)
+            .map(|e| e.as_secs())
+            .map_err(io::Error::other)?;
+
+        header.set_mtime(mtime);
+        header.set_mode(permissions_to_u32(metadata.permissions()));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            header.set_uid(metadata.uid() as u64);
+            header.set_gid(metadata.gid() as u64);
+        }
+        #[cfg(not(unix))]
+        {
+            eprintln!("warning: nonunix environment. dropping uid, gid.");
+        }
+
+        let (filemode, data) = if metadata.is_dir() {
+            (FileMode::Directory, Data::Empty)
+        } else if metadata.is_file() {
+            header.set_size(metadata.len());
+            (FileMode::File, Data::File(pth_clean.clone()))
+        } else if metadata.file_type().is_symlink() {
+            let link = pth.strip_prefix(source).unwrap_or(pth);
+            let target = fs::read_link(pth)?;
+            self.set_symlink(&mut header, pth_clean_str, link, &target)?;
+            (FileMode::Symlink, Data::Empty)
+        } else if let Some((filemode, major, minor)) = special_file(&metadata)
+            && self.unsupported == Some(Unsupported::Archive)
+        {
+            self.set_special(&mut header, filemode, major, minor)?;
+            (filemode, Data::Empty)
+        } else {
+            self.unsupported(pth_clean_str)?;
+            return Ok(None);
+        };
+
+        let mut link_key = None;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            if filemode == FileMode::File && metadata.nlink() > 1 {
+                link_key = Some(LinkKey::Inode(metadata.dev(), metadata.ino()));
+            }
+        }
+
+        Ok(Some(Entry {
+            path: pth_clean,
+            filemode,
+            header,
+            data,
+            link_key,
+        }))
+    }
+
+    /// unpack collects archive entries from an existing tarball.
+    ///
+    /// Entry content is spooled to a temporary file, in order to support sorting.
+    fn unpack(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        source: &path::Path,
+    ) -> Result<Vec<Entry>, io::Error> {
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut file_indices: HashMap<path::PathBuf, usize> = HashMap::new();
+        let mut archive = open_archive(source)?;
+        let mut spool_writer = SpoolWriter::new()?;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+
+            // Pax metadata entries (e.g. from `git archive`) describe other entries, not files.
+            if matches!(
+                entry.header().entry_type(),
+                tar::EntryType::XGlobalHeader | tar::EntryType::XHeader
+            ) {
+                continue;
+            }
+
+            let pth_clean = entry.path()?.normalize();
+            let pth_clean_str = pth_clean.to_str().ok_or_else(|| {
+                io::Error::other(format!("unable to render path {:?}", pth_clean))
+            })?;
+
+            if pth_clean_str.is_empty() || pth_clean_str == "." {
+                continue;
+            }
+
+            if self.is_skip_relative(
+                skip_path_pattern,
+                path::Path::new(""),
+                &pth_clean,
+                entry.header().entry_type().is_dir(),
+                pth_clean_str,
+            )? {
+                continue;
+            }
+
+            let source_header = entry.header().clone();
+            let mut header = self.new_header();
+            header.set_mtime(source_header.mtime()?);
+            header.set_mode(source_header.mode()?);
+            header.set_uid(source_header.uid()?);
+            header.set_gid(source_header.gid()?);
+
+            if let Some(username) = source_header.username().map_err(io::Error::other)? {
+                header.set_username(username)?;
+            }
+
+            if let Some(groupname) = source_header.groupname().map_err(io::Error::other)? {
+                header.set_groupname(groupname)?;
+            }
+
+            let entry_type = source_header.entry_type();
+            let mut link_target = None;
+
+            let (filemode, data) = if entry_type.is_dir() {
+                (FileMode::Directory, Data::Empty)
+            } else if entry_type.is_file() {
+                let (data, len) = spool_writer.append(&mut entry)?;
+                header.set_size(len);
+                (FileMode::File, data)
+            } else if entry_type.is_hard_link() {
+                // Materialize hard links, so that sorting may reassign link targets.
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| {
+                        io::Error::other(format!("missing hard link target: {pth_clean_str}"))
+                    })?
+                    .normalize();
+                let target_entry =
+                    file_indices
+                        .get(&target)
+                        .map(|i| &entries[*i])
+                        .ok_or_else(|| {
+                            io::Error::other(format!(
+                                "hard link target unavailable: {pth_clean_str} -> {}",
+                                target.display()
+                            ))
+                        })?;
+                let Data::Spool(spool, offset, len) = &target_entry.data else {
+                    return Err(io::Error::other(format!(
+                        "hard link target unavailable: {pth_clean_str} -> {}",
+                        target.display()
+                    )));
+                };
+                header.set_size(*len);
+                link_target = target_entry.link_key.as_ref().and_then(|e| match e {
+                    LinkKey::Path(pth) => Some(pth.clone()),
+                    _ => None,
+                });
+                (
+                    FileMode::File,
+                    Data::Spool(sync::Arc::clone(spool), *offset, *len),
+                )
+            } else if entry_type.is_symlink() {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| {
+                        io::Error::other(format!("missing symlink target: {pth_clean_str}"))
+                    })?
+                    .to_path_buf();
+                self.set_symlink(&mut header, pth_clean_str, &pth_clean, &target)?;
+                (FileMode::Symlink, Data::Empty)
+            } else if let Some(filemode) = match entry_type {
+                tar::EntryType::Fifo => Some(FileMode::Fifo),
+                tar::EntryType::Char => Some(FileMode::CharDevice),
+                tar::EntryType::Block => Some(FileMode::BlockDevice),
+                _ => None,
+            } && self.unsupported == Some(Unsupported::Archive)
+            {
+                let major = source_header.device_major()?.unwrap_or(0);
+                let minor = source_header.device_minor()?.unwrap_or(0);
+                self.set_special(&mut header, filemode, major, minor)?;
+                (filemode, Data::Empty)
+            } else {
+                self.unsupported(pth_clean_str)?;
+                continue;
+            };
+
+            let link_key = match filemode {
+                FileMode::File => Some(LinkKey::Path(link_target.unwrap_or(pth_clean.clone()))),
+                _ => None,
+            };
+
+            if filemode == FileMode::File {
+                file_indices.insert(pth_clean.clone(), entries.len());
+            }
+
+            entries.push(Entry {
+                path: pth_clean,
+                filemode,
+                header,
+                data,
+                link_key,
+            });
+        }
+
+        spool_writer.finish()?;
+        Ok(entries)
+    }
+
+    /// git_tree collects archive entries for the git tracked files beneath a source directory.
+    fn git_tree(
+        &self,
+        skip_path_pattern: &fancy_regex::Regex,
+        source: &path::Path,
+    ) -> Result<Vec<Entry>, io::Error> {
+        let git_config = self.git.clone().unwrap_or_default();
+        let revision = git_config.revision.as_deref();
+        let mut mtime = 0;
+
+        if let Some(true) = git_config.commit_mtime {
+            let commit = format!("{}^{{commit}}", revision.unwrap_or("HEAD"));
+            let output = git(source, ["show", "-s", "--format=%ct", &commit])?;
+            mtime = String::from_utf8_lossy(&output)
+                .trim()
+                .parse::<u64>()
+                .map_err(io::Error::other)?;
+        }
+
+        let objects: Vec<GitObject> = list_git_objects(source, revision)?
+            .into_iter()
+            .filter(|e| {
+                // Submodules (gitlinks) lack content in this repository.
+                let submodule = e.mode == 0o160000;
+
+                if submodule {
+                    eprintln!(
+                        "warning: skipping submodule: {}",
+                        source.join(&e.path).normalize().display()
+                    );
+                }
+
+                !submodule
+            })
+            .collect();
+        let source_abs = source.canonicalize()?;
+        let mut directories: Vec<path::PathBuf> = Vec::new();
+        let mut tracked: Vec<&GitObject> = Vec::new();
+
+        for object in &objects {
+            directories.extend(object.path.ancestors().skip(1).map(|e| e.to_path_buf()));
+
+            if self.is_skip_tracked(skip_path_pattern, source, &source_abs, &object.path, false)? {
+                continue;
+            }
+
+            tracked.push(object);
+        }
+
+        let ids: Vec<&str> = tracked.iter().map(|e| e.id.as_str()).collect();
+        let blobs = read_git_blobs(source, &ids)?;
+        let mut entries = Vec::new();
+
+        for (object, (data, len)) in tracked.into_iter().zip(blobs) {
+            let pth_clean = source.join(&object.path).normalize();
+            let pth_clean_str = pth_clean.to_str().ok_or_else(|| {
+                io::Error::other(format!("unable to render path {:?}", pth_clean))
+            })?;
+
+            let mut header = self.new_header();
+            header.set_mtime(mtime);
+            header.set_uid(0);
+            header.set_gid(0);
+
+            if object.mode == 0o120000 {
+                let target =
+                    path::PathBuf::from(String::from_utf8(data.read()?).map_err(io::Error::other)?);
+                header.set_mode(0o777);
+                self.set_symlink(&mut header, pth_clean_str, &object.path, &target)?;
+                entries.push(Entry {
+                    path: pth_clean,
+                    filemode: FileMode::Symlink,
+                    header,
+                    data: Data::Empty,
+                    link_key: None,
+                });
+                continue;
+            }
+
+            header.set_mode(if object.mode & 0o111 != 0 {
+                0o755
+            } else {
+                0o644
+            });
+            header.set_size(len);
+            entries.push(Entry {
+                path: pth_clean.clone(),
+                filemode: FileMode::File,
+                header,
+                data,
+                link_key: Some(LinkKey::Path(pth_clean)),
+            });
+        }
+
+        directories.sort();
+        directories.dedup();
+
+        for directory in directories {
+            if self.is_skip_tracked(skip_path_pattern, source, &source_abs, &directory, true)? {
+                continue;
+            }
+
+            let directory = source.join(directory).normalize();
+
+            // Source roots such as "." do not enter the archive.
+            if directory.as_os_str().is_empty() || directory == path::Path::new(".") {
+                continue;
+            }
+
+            let mut header = self.new_header();
+            header.set_mtime(mtime);
+            header.set_mode(0o755);
+            header.set_uid(0);
+            header.set_gid(0);
+            entries.push(Entry {
+                path: directory,
+                filemode: FileMode::Directory,
+                header,
+                data: Data::Empty,
+                link_key: None,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// resolve_compression selects a compression format for an archive path.
+    ///
+    /// Without an explicit `compression` setting, the format follows the file extension,
+    /// falling back to `DEFAULT_COMPRESSION`.
+    /// An explicit setting that conflicts with a recognized file extension presents an error.
+    pub fn resolve_compression(&self, target: &path::Path) -> Result<Compression, io::Error> {
+        let inferred_option = Compression::from_path(target);
+
+        match (self.compression, inferred_option) {
+            (Some(compression), Some(inferred)) if !compression.is_kind(&inferred) => {
+                Err(io::Error::other(format!(
+                    "compression {} conflicts with {} file extension: {}",
+                    compression.name(),
+                    inferred.name(),
+                    target.display()
+                )))
+            }
+            (Some(compression), _) => Ok(compression),
+            (None, Some(inferred)) => Ok(inferred),
+            (None, None) => Ok(DEFAULT_COMPRESSION),
+        }
+    }
+
+    /// write emits a tarball to a file path.
+    fn write(&self, target: &path::Path, entries: Vec<Entry>) -> Result<(), io::Error> {
+        let compression = match self.codec {
+            Some(_) => None,
+            None => Some(self.resolve_compression(target)?),
+        };
+
+        let file = fs::File::create(target)?;
+        self.write_to(Box::new(file), compression, entries)
+    }
+
+    /// write_to applies rules to sorted entries, and emits a tarball to a sink.
+    ///
+    /// Compression follows `codec`, then the given compression,
+    /// then the `compression` setting, then `DEFAULT_COMPRESSION`.
+    fn write_to<'a>(
+        &self,
+        sink: Box<dyn io::Write + 'a>,
+        compression: Option<Compression>,
+        entries: Vec<Entry>,
+    ) -> Result<(), io::Error> {
+        let rules: &Vec<Rule> = match (&self.rules, self.default_rules) {
+            (Some(rules), _) => rules,
+            (None, Some(DefaultRules::Content)) => &CONTENT_RULES,
+            _ => &DEFAULT_RULES,
+        };
+        let examine_content = rules.iter().any(|e| e.has_content_condition());
+        let special_path_pattern = match &self.special_paths {
+            Some(special_paths) if !special_paths.is_empty() => {
+                Some(generate_include_path_pattern(special_paths).map_err(io::Error::other)?)
+            }
+            _ => None,
+        };
+
+        let encoder = match &self.codec {
+            Some(codec) => codec.encoder(sink)?,
+            None => match compression
+                .or(self.compression)
+                .unwrap_or(DEFAULT_COMPRESSION)
+            {
+                Compression::Gzip => self.gzip.clone().unwrap_or_default().encoder(sink)?,
+                compression => compression.encoder(sink)?,
+            },
+        };
+        let mut builder = tar::Builder::new(encoder);
+        let links = resolve_hard_links(&entries, self.deduplicate.unwrap_or(false))?;
+        let prefix = self.prefix_path();
+
+        // Prefixes apply after rules, so that rules match unprefixed paths.
+        if let Some(prefix) = &prefix {
+            for entry in self.prefix_entries(prefix, &entries)? {
+                let mut header = entry.header;
+                header.set_path(&entry.path)?;
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+
+                if let Some(true) = self.verbose {
+                    eprintln!("a {}", entry.path.display());
+                }
+
+                header.set_cksum();
+                builder.append(&header, &[] as &[u8])?;
+            }
+        }
+
+        for (entry, link) in entries.into_iter().zip(links) {
+            let Entry {
+                path: pth_clean,
+                filemode,
+                mut header,
+                mut data,
+                ..
+            } = entry;
+            let pth_clean_str = pth_clean.to_str().ok_or_else(|| {
+                io::Error::other(format!("unable to render path {:?}", pth_clean))
+            })?;
+            let head = match filemode {
+                FileMode::File if examine_content => Some(data.head()?),
+                _ => None,
+            };
+
+            let (pth_archive, link) = match &prefix {
+                Some(prefix) => (
+                    prefix.join(&pth_clean),
+                    link.map(|target| prefix.join(target)),
+                ),
+                None => (pth_clean.clone(), link),
+            };
+            header.set_path(&pth_archive)?;
+
+            if filemode == FileMode::Directory {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+            }
+
+            if let Some(target) = &link {
+                header.set_entry_type(tar::EntryType::Link);
+                header.set_link_name(target)?;
+                header.set_size(0);
+                data = Data::Empty;
+            }
+
+            if let Some(true) = self.verbose {
+                match &link {
+                    Some(target) => {
+                        eprintln!("a {} link to {}", pth_archive.display(), target.display())
+                    }
+                    None => eprintln!("a {}", pth_archive.display()),
+                }
+            }
+
+            for rule in rules {
+                if !rule.is_match_content(&filemode, pth_clean_str, head.as_deref())? {
+                    continue;
+                }
+
+                rule.apply(&mut header)?;
+            }
+
+            self.limit_permissions(
+                special_path_pattern.as_ref(),
+                filemode,
+                pth_clean_str,
+                &mut header,
+            )?;
+            header.set_cksum();
+
+            builder.append(&header, data.open()?)?;
+        }
+
+        builder.into_inner()?.finish().map(|_| ())
+    }
+
+    /// limit_permissions applies the `max_permissions` ceiling to an entry.
+    ///
+    /// When `special_paths` is configured, only matching entries retain special bits.
+    ///
+    /// Symbolic links are exempt, as their permissions carry no meaning.
+    fn limit_permissions(
+        &self,
+        special_path_pattern: Option<&fancy_regex::Regex>,
+        filemode: FileMode,
+        pth_clean_str: &str,
+        header: &mut tar::Header,
+    ) -> Result<(), io::Error> {
+        if (self.max_permissions.is_none() && special_path_pattern.is_none())
+            || filemode == FileMode::Symlink
+        {
+            return Ok(());
+        }
+
+        let mut mask = self.max_permissions.unwrap_or(!0);
+
+        if let Some(special_path_pattern) = special_path_pattern {
+            if special_path_pattern
+                .is_match(pth_clean_str)
+                .map_err(|e| io::Error::other(e.to_string()))?
+            {
+                mask |= SPECIAL_PERMISSIONS;
+            } else {
+                mask &= !SPECIAL_PERMISSIONS;
+            }
+        }
+
+        let mode = header.mode()?;
+        let stripped = mode & !mask;
+
+        if stripped == 0 {
+            return Ok(());
+        }
+
+        if let Some(true) = self.verbose {
+            eprintln!(
+                "strip {pth_clean_str}: {}",
+                permission_bit_names(stripped).join(", ")
+            );
+        }
+
+        header.set_mode(mode & mask);
+        Ok(())
+    }
+
+    /// skip_path_pattern compiles the configured exclusions.
+    fn skip_path_pattern(&self) -> Result<fancy_regex::Regex, io::Error> {
+        generate_skip_path_pattern(self.skip_paths.as_ref().unwrap_or(&DEFAULT_SKIP_PATHS))
+            .map_err(io::Error::other)
+    }
+
+    /// include_path_pattern compiles `include_paths` to a regex,
+    /// or else None when all paths are included.
+    fn include_path_pattern(&self) -> Result<Option<fancy_regex::Regex>, io::Error> {
+        match &self.include_paths {
+            Some(include_paths) if !include_paths.is_empty() => {
+                generate_include_path_pattern(include_paths)
+                    .map(Some)
+                    .map_err(io::Error::other)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// include retains entries matching an include path pattern,
+    /// along with their ancestor directories.
+    ///
+    /// Patterns match paths beneath a source root, joined onto a base path,
+    /// following `skip_match`.
+    fn include(
+        &self,
+        include_path_pattern: Option<&fancy_regex::Regex>,
+        source_root: &path::Path,
+        base: &path::Path,
+        entries: Vec<Entry>,
+    ) -> Result<Vec<Entry>, io::Error> {
+        let Some(include_path_pattern) = include_path_pattern else {
+            return Ok(entries);
+        };
+
+        let mut included: HashSet<path::PathBuf> = HashSet::new();
+
+        for entry in &entries {
+            let relative = entry.path.strip_prefix(source_root).unwrap_or(&entry.path);
+            let subject = match (relative.as_os_str().is_empty(), entry.path.file_name()) {
+                // File sources match by name, as with skip_paths.
+                (true, Some(name))
+                    if base.as_os_str().is_empty() && entry.filemode != FileMode::Directory =>
+                {
+                    path::PathBuf::from(name)
+                }
+                (true, _) => base.to_path_buf(),
+                (false, _) => base.join(relative),
+            };
+            let pth_str = subject
+                .to_str()
+                .ok_or_else(|| io::Error::other(format!("unable to render path {:?}", subject)))?;
+
+            if include_path_pattern
+                .is_match(pth_str)
+                .map_err(|e| io::Error::other(e.to_string()))?
+            {
+                included.extend(entry.path.ancestors().map(|e| e.to_path_buf()));
+            }
+        }
+
+        Ok(entries
+            .into_iter()
+            .filter(|e| {
+                let is_included = included.contains(&e.path);
+
+                if !is_included && let Some(true) = self.verbose {
+                    eprintln!("skipping {}", e.path.display());
+                }
+
+                is_included
+            })
+            .collect())
+    }
+
+    /// map applies the first matching mapping to an entry path.
+    ///
+    /// Returns false for entries mapped to an empty path, which drop out of the archive.
+    fn map(&self, entry: &mut Entry) -> Result<bool, io::Error> {
+        let Some(mappings) = &self.mappings else {
+            return Ok(true);
+        };
+
+        let pth_str = entry
+            .path
+            .to_str()
+            .ok_or_else(|| io::Error::other(format!("unable to render path {:?}", entry.path)))?;
+
+        for mapping in mappings {
+            let Some(dest) = mapping.map(pth_str)? else {
+                continue;
+            };
+
+            let dest_path = path::Path::new(&dest);
+
+            // Destinations remain relative to the archive root.
+            if dest_path.has_root()
+                || dest_path
+                    .components()
+                    .any(|e| e == path::Component::ParentDir)
+            {
+                return Err(io::Error::other(format!(
+                    "mapping destination escapes archive root: {dest} from mapping {:?}",
+                    mapping
+                )));
+            }
+
+            let dest_clean = dest_path.normalize();
+            let dest_clean_str = dest_clean.to_string_lossy();
+            let omit = dest_clean_str.is_empty() || dest_clean_str == ".";
+
+            if let Some(true) = self.verbose {
+                if omit {
+                    eprintln!("map {pth_str} -> (omitted)");
+                } else {
+                    eprintln!("map {pth_str} -> {dest_clean_str}");
+                }
+            }
+
+            if omit {
+                return Ok(false);
+            }
+
+            entry.path = dest_clean;
+            break;
+        }
+
+        Ok(true)
+    }
+
+    /// prefix_path cleans the configured prefix directory, when nonempty.
+    fn prefix_path(&self) -> Option<path::PathBuf> {
+        let prefix_clean = path::Path::new(self.prefix.as_ref()?).normalize();
+
+        if prefix_clean.as_os_str().is_empty() || prefix_clean == path::Path::new(".") {
+            return None;
+        }
+
+        Some(prefix_clean)
+    }
+
+    /// prefix_entries generates entries for a prefix directory and its ancestors,
+    /// given the entries nested beneath it.
+    ///
+    /// Prefix directories assume the latest modification time among entries.
+    fn prefix_entries(
+        &self,
+        prefix: &path::Path,
+        entries: &[Entry],
+    ) -> Result<Vec<Entry>, io::Error> {
+        let mut mtime = 0;
+
+        for entry in entries {
+            mtime = mtime.max(entry.header.mtime()?);
+        }
+
+        let mut prefix_entries = Vec::new();
+
+        for ancestor in prefix.ancestors() {
+            if ancestor.as_os_str().is_empty() {
+                continue;
+            }
+
+            let mut header = self.new_header();
+            header.set_mtime(mtime);
+            header.set_mode(0o755);
+            header.set_uid(0);
+            header.set_gid(0);
+
+            prefix_entries.push(Entry {
+                path: ancestor.to_path_buf(),
+                filemode: FileMode::Directory,
+                header,
+                data: Data::Empty,
+                link_key: None,
+            });
+        }
+
+        prefix_entries.reverse();
+        self.apply_ownership(&mut prefix_entries)?;
+        Ok(prefix_entries)
+    }
+
+    /// apply_mtime applies the mtime policy to entries,
+    /// given an optional SOURCE_DATE_EPOCH value.
+    fn apply_mtime(
+        &self,
+        entries: &mut [Entry],
+        source_date_epoch: Option<u64>,
+    ) -> Result<(), io::Error> {
+        let policy = match (self.mtime, source_date_epoch) {
+            (Some(policy), _) => policy,
+            (None, Some(_)) => Mtime::Clamp { epoch: None },
+            (None, None) => Mtime::Preserve,
+        };
+
+        let (epoch, clamp) = match policy {
+            Mtime::Preserve | Mtime::Git => return Ok(()),
+            Mtime::Replace { epoch } => (epoch, false),
+            Mtime::Clamp { epoch } => (epoch, true),
+        };
+
+        let epoch = epoch.or(source_date_epoch).ok_or_else(|| {
+            io::Error::other(format!(
+                "mtime policy {policy:?} requires an epoch or {SOURCE_DATE_EPOCH_VARIABLE}"
+            ))
+        })?;
+
+        for entry in entries {
+            if !clamp || entry.header.mtime()? > epoch {
+                entry.header.set_mtime(epoch);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// apply_git_mtime assigns modification times from git history,
+    /// given entries tagged with the index of their source.
+    ///
+    /// History follows `git.revision` when configured, else `HEAD`.
+    fn apply_git_mtime<P: AsRef<path::Path>>(
+        &self,
+        sources: &[P],
+        entries: &mut [(usize, Entry)],
+    ) -> Result<(), io::Error> {
+        let revision = self
+            .git
+            .as_ref()
+            .and_then(|e| e.revision.clone())
+            .unwrap_or("HEAD".to_string());
+        let cwd = env::current_dir()?;
+        let mut toplevels: Vec<path::PathBuf> = Vec::new();
+        let mut directory_toplevels: HashMap<path::PathBuf, path::PathBuf> = HashMap::new();
+        let mut histories: HashMap<path::PathBuf, HashMap<path::PathBuf, u64>> = HashMap::new();
+
+        for source in sources {
+            let source = source.as_ref();
+            let dir = match source.parent() {
+                Some(parent) if !source.is_dir() && !parent.as_os_str().is_empty() => parent,
+                _ if !source.is_dir() => path::Path::new("."),
+                _ => source,
+            };
+
+            // Manifests list many files per directory, so resolve each directory once.
+            let toplevel = match directory_toplevels.get(dir) {
+                Some(toplevel) => toplevel.clone(),
+                None => {
+                    let toplevel = path::PathBuf::from(
+                        String::from_utf8_lossy(&git(dir, ["rev-parse", "--show-toplevel"])?)
+                            .trim_end(),
+                    );
+                    directory_toplevels.insert(dir.to_path_buf(), toplevel.clone());
+                    toplevel
+                }
+            };
+
+            if !histories.contains_key(&toplevel) {
+                histories.insert(toplevel.clone(), git_history(&toplevel, &revision)?);
+            }
+
+            toplevels.push(toplevel);
+        }
+
+        let mut directory_mtimes: HashMap<path::PathBuf, u64> = HashMap::new();
+
+        for (i, entry) in entries.iter_mut() {
+            if entry.filemode == FileMode::Directory {
+                continue;
+            }
+
+            let toplevel = &toplevels[*i];
+            let pth_abs = cwd.join(&entry.path).normalize();
+
+            if let Ok(repository_path) = pth_abs.strip_prefix(toplevel)
+                && let Some(mtime) = histories[toplevel].get(repository_path)
+            {
+                entry.header.set_mtime(*mtime);
+            }
+
+            let mtime = entry.header.mtime()?;
+
+            for ancestor in entry.path.ancestors().skip(1) {
+                let directory_mtime = directory_mtimes.entry(ancestor.to_path_buf()).or_insert(0);
+                *directory_mtime = (*directory_mtime).max(mtime);
+            }
+        }
+
+        for (_, entry) in entries.iter_mut() {
+            if entry.filemode == FileMode::Directory
+                && let Some(mtime) = directory_mtimes.get(&entry.path)
+            {
+                entry.header.set_mtime(*mtime);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// apply_ownership applies the ownership policy to entries.
+    fn apply_ownership(&self, entries: &mut [Entry]) -> Result<(), io::Error> {
+        let ownership = self.ownership.as_ref().unwrap_or(&DEFAULT_OWNERSHIP);
+        let (mut usernames, mut groupnames) = (HashMap::new(), HashMap::new());
+        let (mut uid_map, mut gid_map) = (HashMap::new(), HashMap::new());
+
+        match ownership {
+            Ownership::Preserve => {
+                usernames = load_id_names(PASSWD_PATH)?;
+                groupnames = load_id_names(GROUP_PATH)?;
+            }
+            Ownership::Map { uids, gids } => {
+                uid_map = parse_id_map(uids)?;
+                gid_map = parse_id_map(gids)?;
+            }
+            Ownership::Anonymize { .. } => {}
+        }
+
+        for entry in entries.iter_mut() {
+            let header = &mut entry.header;
+
+            // Vintage headers lack name fields.
+            let has_names = header.as_ustar().is_some() || header.as_gnu().is_some();
+
+            match ownership {
+                Ownership::Anonymize {
+                    uid,
+                    gid,
+                    username,
+                    groupname,
+                } => {
+                    header.set_uid(uid.unwrap_or(0));
+                    header.set_gid(gid.unwrap_or(0));
+
+                    if has_names {
+                        header.set_username(username.as_deref().unwrap_or("root"))?;
+                        header.set_groupname(groupname.as_deref().unwrap_or("root"))?;
+                    }
+                }
+                Ownership::Map { .. } => {
+                    let (uid, gid) = (header.uid()?, header.gid()?);
+                    header.set_uid(*uid_map.get(&uid).unwrap_or(&uid));
+                    header.set_gid(*gid_map.get(&gid).unwrap_or(&gid));
+
+                    if has_names {
+                        header.set_username("")?;
+                        header.set_groupname("")?;
+                    }
+                }
+                Ownership::Preserve => {
+                    if !has_names {
+                        continue;
+                    }
+
+                    let missing_username = header
+                        .username()
+                        .map_err(io::Error::other)?
+                        .is_none_or(|e| e.is_empty());
+
+                    if missing_username && let Some(username) = usernames.get(&header.uid()?) {
+                        header.set_username(username)?;
+                    }
+
+                    let missing_groupname = header
+                        .groupname()
+                        .map_err(io::Error::other)?
+                        .is_none_or(|e| e.is_empty());
+
+                    if missing_groupname && let Some(groupname) = groupnames.get(&header.gid()?) {
+                        header.set_groupname(groupname)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// check_tarbomb detects archives with more than one top level path,
+    /// which would interleave with user files when extracted.
+    fn check_tarbomb(&self, entries: &[Entry]) -> Result<(), io::Error> {
+        let tarbomb = self.tarbomb.unwrap_or(DEFAULT_TARBOMB);
+
+        // Prefixes nest every entry beneath a single top level directory.
+        if tarbomb == Tarbomb::Allow || self.prefix_path().is_some() {
+            return Ok(());
+        }
+
+        let mut roots: Vec<&ffi::OsStr> = entries
+            .iter()
+            .filter_map(|e| e.path.components().next())
+            .map(|e| e.as_os_str())
+            .collect();
+        roots.dedup();
+
+        if roots.len() < 2 {
+            return Ok(());
+        }
+
+        let message = format!(
+            "archive lacks a single top level directory: {}",
+            roots
+                .iter()
+                .map(|e| e.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        if tarbomb == Tarbomb::Error {
+            return Err(io::Error::other(message));
+        }
+
+        eprintln!("warning: {message}");
+        Ok(())
+    }
+
+    /// collect gathers archive entries from several sources,
+    /// merging them into a single, globally sorted sequence.
+    ///
+    /// worktree denotes sources on the filesystem, rather than existing tarballs.
+    /// rooted denotes collectors yielding paths beneath each source root,
+    /// rather than manifest or archive paths.
+    fn collect<P: AsRef<path::Path>>(
+        &self,
+        sources: &[P],
+        collector: fn(&Self, &fancy_regex::Regex, &path::Path) -> Result<Vec<Entry>, io::Error>,
+        worktree: bool,
+        rooted: bool,
+    ) -> Result<Vec<Entry>, io::Error> {
+        if sources.is_empty() {
+            return Err(io::Error::other("missing source"));
+        }
+
+        let skip_path_pattern = self.skip_path_pattern()?;
+        let include_path_pattern = self.include_path_pattern()?;
 
         if let Some(cwd_pathbuf) = &self.cwd {
             env::set_current_dir(cwd_pathbuf.as_path())?;
         }
 
-        let file = fs::File::create(target)?;
-        let gz_encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
-        let mut builder = tar::Builder::new(gz_encoder);
-        let walker = walkdir::WalkDir::new(source).sort_by(
-            |a: &walkdir::DirEntry, b: &walkdir::DirEntry| a.file_name().cmp(b.file_name()),
-        );
-
-        for entry in walker {
-            let entry = entry?;
-            let pth = entry.path();
-            let pth_clean = pth.normalize();
-            let pth_clean_str = pth_clean.to_str().ok_or_else(|| {
-                io::Error::other(format!("unable to render path {:?}", pth_clean))
-            })?;
+        let mut source_entries: Vec<(usize, Entry)> = Vec::new();
 
-            if pth_clean_str.is_empty() || pth_clean_str == "." {
-                continue;
-            }
+        let absolute =
+            worktree && self.skip_match.unwrap_or(DEFAULT_SKIP_MATCH) == SkipMatch::Absolute;
 
-            let pth_abs = pth.canonicalize()?;
-            let pth_abs_str = pth_abs.to_str().ok_or(io::Error::other(format!(
-                "unable to process path: {}",
-                pth_abs.display()
-            )))?;
+        for (i, source) in sources.iter().enumerate() {
+            let source = source.as_ref();
+            let entries = collector(self, &skip_path_pattern, source)?;
+            let source_root = match rooted {
+                true => source.normalize(),
+                false => path::PathBuf::new(),
+            };
+            let base = match (absolute, rooted) {
+                (false, _) => path::PathBuf::new(),
+                (true, true) => source.canonicalize()?,
+                (true, false) => env::current_dir()?,
+            };
 
-            if skip_path_pattern
-                .is_match(pth_abs_str)
-                .map_err(|e| io::Error::other(e.to_string()))?
+            for entry in
+                self.include(include_path_pattern.as_ref(), &source_root, &base, entries)?
             {
-                if let Some(true) = self.verbose {
-                    eprintln!("skipping {pth_clean_str}");
-                }
+                source_entries.push((i, entry));
+            }
+        }
 
-                continue;
+        if let Some(Mtime::Git) = self.mtime {
+            if !worktree {
+                return Err(io::Error::other(
+                    "Git mtime policy requires directory or git sources",
+                ));
             }
 
-            let metadata = entry.metadata()?;
-            let mut header = match self.header {
-                Some(HeaderType::Gnu) => tar::Header::new_gnu(),
-                Some(HeaderType::TarV7) => tar::Header::new_old(),
-                _ => tar::Header::new_ustar(),
-            };
+            self.apply_git_mtime(sources, &mut source_entries)?;
+        }
 
-            header.set_path(&pth_clean)?;
+        let mut tagged_entries: Vec<(usize, Entry)> = Vec::new();
 
-            let mtime = metadata
-                .modified()?
-                .duration_since(time::UNIX_EPOCH)
-                .map(|e| e.as_secs())
-                .map_err(io::Error::other)?;
+        for (i, mut entry) in source_entries {
+            if !self.map(&mut entry)? {
+                continue;
+            }
 
-            header.set_mtime(mtime);
-            header.set_mode(permissions_to_u32(metadata.permissions()));
+            tagged_entries.push((i, entry));
+        }
 
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::MetadataExt;
-                header.set_uid(metadata.uid() as u64);
-                header.set_gid(metadata.gid() as u64);
-            }
-            #[cfg(not(unix))]
-            {
-                eprintln!("warning: nonunix environment. dropping uid, gid.");
-            }
+        // Component-wise path ordering matches a sorted, depth first directory walk.
+        tagged_entries.sort_by(|a, b| a.1.path.cmp(&b.1.path));
 
-            let filemode = if metadata.is_dir() {
-                FileMode::Directory
-            } else if metadata.is_file() {
-                FileMode::File
-            } else {
+        for pair in tagged_entries.windows(2) {
+            let ((i, a), (j, b)) = (&pair[0], &pair[1]);
+
+            if a.path == b.path {
                 return Err(io::Error::other(format!(
-                    "unsupported file type: {pth_clean_str}"
+                    "duplicate archive path: {} (sources: {}, {})",
+                    a.path.display(),
+                    sources[*i].as_ref().display(),
+                    sources[*j].as_ref().display()
                 )));
-            };
-
-            if filemode == FileMode::Directory {
-                header.set_entry_type(tar::EntryType::Directory);
-                header.set_size(0);
-            } else if filemode == FileMode::File {
-                header.set_size(metadata.len());
             }
+        }
 
-            if let Some(true) = self.verbose {
-                eprintln!("a {pth_clean_str}");
-            }
+        let mut entries: Vec<Entry> = tagged_entries.into_iter().map(|(_, e)| e).collect();
+        self.apply_ownership(&mut entries)?;
+        self.apply_mtime(&mut entries, source_date_epoch()?)?;
+        self.check_tarbomb(&entries)?;
+        Ok(entries)
+    }
 
-            for rule in rules {
-                if !rule.is_match(&filemode, pth_clean_str)? {
-                    continue;
-                }
+    /// tree_collector selects a directory tree enumeration strategy,
+    /// either the filesystem or else git records.
+    fn tree_collector(
+        &self,
+    ) -> fn(&Self, &fancy_regex::Regex, &path::Path) -> Result<Vec<Entry>, io::Error> {
+        match self.git {
+            Some(_) => Chandler::git_tree,
+            None => Chandler::walk,
+        }
+    }
 
-                rule.apply(&mut header)?;
-            }
+    /// archive generates a tarball from directory trees.
+    ///
+    /// When `git` is configured, directory trees enumerate git tracked files.
+    pub fn archive<P: AsRef<path::Path>>(
+        &self,
+        target: &path::Path,
+        sources: &[P],
+    ) -> Result<(), io::Error> {
+        let entries = self.collect(sources, self.tree_collector(), true, true)?;
+        self.write(target, entries)
+    }
 
-            header.set_cksum();
+    /// normalize generates a tarball from existing tarballs (TGZ, TAR.GZ, TAR.ZST, TAR.XZ, TAR.BZ2, TAR).
+    ///
+    /// Entries pass through the same exclusions, rules, and sorting as directory trees.
+    pub fn normalize<P: AsRef<path::Path>>(
+        &self,
+        target: &path::Path,
+        sources: &[P],
+    ) -> Result<(), io::Error> {
+        let entries = self.collect(sources, Chandler::unpack, false, false)?;
+        self.write(target, entries)
+    }
 
-            if filemode == FileMode::Directory {
-                builder.append(&header, &[] as &[u8])?;
-            } else if filemode == FileMode::File {
-                let mut source_file = fs::File::open(pth_clean)?;
-                builder.append(&header, &mut source_file)?;
-            }
+    /// archive_paths generates a tarball from an explicit list of paths, such as a build manifest.
+    ///
+    /// Each path contributes a single entry, without recursing into directories.
+    /// Entries pass through the same exclusions, rules, and sorting as directory trees.
+    pub fn archive_paths<I: IntoIterator<Item = P>, P: AsRef<path::Path>>(
+        &self,
+        target: &path::Path,
+        paths: I,
+    ) -> Result<(), io::Error> {
+        let paths: Vec<P> = paths.into_iter().collect();
+        let entries = self.collect(&paths, Chandler::list, true, false)?;
+        self.write(target, entries)
+    }
+
+    /// archive_paths_to generates a tarball from an explicit list of paths, emitting to any writer.
+    ///
+    /// Compression follows the `compression` setting, else `DEFAULT_COMPRESSION`.
+    pub fn archive_paths_to<
+        'a,
+        W: io::Write + 'a,
+        I: IntoIterator<Item = P>,
+        P: AsRef<path::Path>,
+    >(
+        &self,
+        sink: W,
+        paths: I,
+    ) -> Result<(), io::Error> {
+        let paths: Vec<P> = paths.into_iter().collect();
+        let entries = self.collect(&paths, Chandler::list, true, false)?;
+        self.write_to(Box::new(sink), None, entries)
+    }
+
+    /// archive_to generates a tarball from directory trees, emitting to any writer,
+    /// such as standard output or an in-memory buffer.
+    ///
+    /// Compression follows the `compression` setting, else `DEFAULT_COMPRESSION`.
+    pub fn archive_to<'a, W: io::Write + 'a, P: AsRef<path::Path>>(
+        &self,
+        sink: W,
+        sources: &[P],
+    ) -> Result<(), io::Error> {
+        let entries = self.collect(sources, self.tree_collector(), true, true)?;
+        self.write_to(Box::new(sink), None, entries)
+    }
+
+    /// normalize_to generates a tarball from existing tarballs, emitting to any writer.
+    ///
+    /// Compression follows the `compression` setting, else `DEFAULT_COMPRESSION`.
+    pub fn normalize_to<'a, W: io::Write + 'a, P: AsRef<path::Path>>(
+        &self,
+        sink: W,
+        sources: &[P],
+    ) -> Result<(), io::Error> {
+        let entries = self.collect(sources, Chandler::unpack, false, false)?;
+        self.write_to(Box::new(sink), None, entries)
+    }
+}
+
+/// CWD_LOCK serializes tests sensitive to the process wide current working directory.
+#[cfg(test)]
+static CWD_LOCK: sync::Mutex<()> = sync::Mutex::new(());
+
+#[test]
+fn test_archive_multiple_sources() -> Result<(), io::Error> {
+    let _cwd_lock = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let original_cwd = env::current_dir()?;
+    let dir = TestDir::new("sources")?;
+    fs::create_dir_all(dir.join("bin"))?;
+    fs::create_dir_all(dir.join("share"))?;
+    fs::create_dir_all(dir.join("bin").join("node_modules"))?;
+    fs::write(dir.join("bin").join("hello"), "hello")?;
+    fs::write(dir.join("bin").join("node_modules").join("lib.js"), "")?;
+    fs::write(dir.join("LICENSE"), "0BSD")?;
+
+    // Skipped directories exclude their contents.
+    let chandler = Chandler {
+        compression: Some(Compression::Uncompressed),
+        cwd: Some(dir.to_path_buf()),
+        skip_paths: Some(vec!["node_modules".to_string()]),
+        ..Chandler::default()
+    };
+    let mut buf = Vec::new();
+    let result = chandler.archive_to(&mut buf, &["share", "LICENSE", "bin"]);
+    let duplicate_result = chandler.archive_to(io::sink(), &["bin", "bin/hello"]);
+    let mut listed_buf = Vec::new();
+    let listed_result = chandler.archive_paths_to(&mut listed_buf, ["share", "./bin/hello"]);
+
+    // Restore the working directory before removing it.
+    env::set_current_dir(&original_cwd)?;
+    result?;
+    listed_result?;
+
+    let mut observed = Vec::new();
+
+    for entry in tar::Archive::new(buf.as_slice()).entries()? {
+        observed.push(entry?.path()?.to_path_buf());
+    }
+
+    let mut listed = Vec::new();
+
+    for entry in tar::Archive::new(listed_buf.as_slice()).entries()? {
+        listed.push(entry?.path()?.to_path_buf());
+    }
+
+    assert_eq!(
+        observed,
+        ["LICENSE", "bin", "bin/hello", "share"]
+            .iter()
+            .map(path::PathBuf::from)
+            .collect::<Vec<path::PathBuf>>()
+    );
+    assert!(duplicate_result.is_err());
+    assert_eq!(
+        listed,
+        ["bin/hello", "share"]
+            .iter()
+            .map(path::PathBuf::from)
+            .collect::<Vec<path::PathBuf>>()
+    );
+    Ok(())
+}
+
+/// test_git runs a git command in a scratch repository with a fixed identity and date,
+/// for testing.
+#[cfg(test)]
+fn test_git(dir: &path::Path, args: &[&str], date: &str) -> Result<(), io::Error> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args([
+            "-c",
+            "user.name=chandler",
+            "-c",
+            "user.email=chandler@example.test",
+            "-c",
+            "commit.gpgsign=false",
+        ])
+        .args(args)
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failure: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_git_tree() -> Result<(), io::Error> {
+    let dir = TestDir::new("git")?;
+    fs::create_dir_all(dir.join("docs"))?;
+    fs::write(dir.join("hello"), "echo hello\n")?;
+    fs::write(dir.join("docs").join("README"), "hello\n")?;
+
+    let git_commands: &[&[&str]] = &[
+        &["init", "-q"],
+        &["add", "hello", "docs"],
+        &["update-index", "--chmod=+x", "hello"],
+        &["commit", "-q", "-m", "hello"],
+    ];
+
+    for args in git_commands {
+        test_git(&dir, args, "1000000000 +0000")?;
+    }
+
+    // Unstaged changes and untracked files do not enter the archive.
+    fs::write(dir.join("hello"), "echo goodbye\n")?;
+    fs::write(dir.join("untracked"), "")?;
+
+    let mut chandler = Chandler {
+        git: Some(Git {
+            revision: Some("HEAD".to_string()),
+            commit_mtime: Some(true),
+        }),
+        ..Chandler::default()
+    };
+    let skip_path_pattern = chandler.skip_path_pattern()?;
+    let revision_result = chandler.git_tree(&skip_path_pattern, &dir);
+
+    chandler.git = Some(Git::default());
+    let index_result = chandler.git_tree(&skip_path_pattern, &dir);
+    let missing_result = read_git_blobs(&dir, &["0000000000000000000000000000000000000000"]);
+
+    // Source roots do not enter the archive as "." entries.
+    let _cwd_lock = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let original_cwd = env::current_dir()?;
+    env::set_current_dir(&dir)?;
+    let cwd_result = chandler.git_tree(&skip_path_pattern, path::Path::new("."));
+    env::set_current_dir(&original_cwd)?;
+
+    let mut observed = Vec::new();
+
+    for entry in revision_result? {
+        observed.push((
+            entry.path.strip_prefix(&dir).unwrap().to_path_buf(),
+            entry.header.mode()?,
+            entry.header.mtime()?,
+            entry.data.read()?,
+        ));
+    }
+
+    observed.sort();
+    assert_eq!(
+        observed,
+        vec![
+            (path::PathBuf::new(), 0o755, 1000000000, Vec::new()),
+            (path::PathBuf::from("docs"), 0o755, 1000000000, Vec::new()),
+            (
+                path::PathBuf::from("docs/README"),
+                0o644,
+                1000000000,
+                b"hello\n".to_vec()
+            ),
+            (
+                path::PathBuf::from("hello"),
+                0o755,
+                1000000000,
+                b"echo hello\n".to_vec()
+            ),
+        ]
+    );
+    assert_eq!(index_result?.len(), 4);
+    assert!(missing_result.is_err());
+
+    let mut cwd_paths: Vec<path::PathBuf> = cwd_result?.into_iter().map(|e| e.path).collect();
+    cwd_paths.sort();
+    assert_eq!(
+        cwd_paths,
+        ["docs", "docs/README", "hello"]
+            .iter()
+            .map(path::PathBuf::from)
+            .collect::<Vec<path::PathBuf>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_walk_ignore_files() -> Result<(), io::Error> {
+    let dir = TestDir::new("ignore-files")?;
+    fs::create_dir_all(dir.join("build"))?;
+    fs::create_dir_all(dir.join("src"))?;
+    fs::write(dir.join(".chandlerignore"), "*.log\nbuild/\n")?;
+    fs::write(dir.join("debug.log"), "")?;
+    fs::write(dir.join("README"), "")?;
+    fs::write(dir.join("build").join(".chandlerignore"), "!*.log\n")?;
+    fs::write(dir.join("build").join("release.log"), "")?;
+    fs::write(dir.join("src").join(".chandlerignore"), "!keep.log\n")?;
+    fs::write(dir.join("src").join("keep.log"), "")?;
+    fs::write(dir.join("src").join("trace.log"), "")?;
+
+    let chandler = Chandler::default();
+    let entries = chandler.walk(&chandler.skip_path_pattern()?, &dir)?;
+    let observed: Vec<path::PathBuf> = entries
+        .iter()
+        .map(|e| e.path.strip_prefix(&dir).unwrap().to_path_buf())
+        .collect();
+
+    // Ignored directories are pruned, so their ignore files cannot re-include contents.
+    assert_eq!(
+        observed,
+        [
+            "",
+            ".chandlerignore",
+            "README",
+            "src",
+            "src/.chandlerignore",
+            "src/keep.log"
+        ]
+        .iter()
+        .map(path::PathBuf::from)
+        .collect::<Vec<path::PathBuf>>()
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_symlinks() -> Result<(), io::Error> {
+    let _cwd_lock = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let original_cwd = env::current_dir()?;
+    let dir = TestDir::new("symlinks")?;
+    fs::create_dir_all(dir.join("hello-1.0.0").join("bin"))?;
+    fs::create_dir_all(dir.join("escape"))?;
+    fs::write(dir.join("hello-1.0.0").join("README"), "hello")?;
+    std::os::unix::fs::symlink(
+        "./../README",
+        dir.join("hello-1.0.0").join("bin").join("readme"),
+    )?;
+    std::os::unix::fs::symlink("../../etc/passwd", dir.join("escape").join("passwd"))?;
+
+    let chandler = Chandler {
+        compression: Some(Compression::Uncompressed),
+        cwd: Some(dir.to_path_buf()),
+        forbid_external_symlinks: Some(true),
+        ..Chandler::default()
+    };
+    let mut buf = Vec::new();
+    let result = chandler.archive_to(&mut buf, &["hello-1.0.0"]);
+    env::set_current_dir(&original_cwd)?;
+    result?;
+
+    let mut observed = Vec::new();
+
+    for entry in tar::Archive::new(buf.as_slice()).entries()? {
+        let entry = entry?;
+
+        if entry.header().entry_type() == tar::EntryType::Symlink {
+            observed.push((
+                entry.path()?.to_path_buf(),
+                entry.link_name()?.map(|e| e.to_path_buf()),
+            ));
         }
+    }
 
-        builder.into_inner()?.finish().map(|_| ())
+    assert_eq!(
+        observed,
+        vec![(
+            path::PathBuf::from("hello-1.0.0/bin/readme"),
+            Some(path::PathBuf::from("../README")),
+        )]
+    );
+
+    // External symlinks are rejected only on request.
+    let skip_path_pattern = chandler.skip_path_pattern()?;
+    assert!(
+        chandler
+            .walk(&skip_path_pattern, &dir.join("escape"))
+            .is_err()
+    );
+
+    let permissive = Chandler::default();
+    let entries = permissive.walk(&skip_path_pattern, &dir.join("escape"))?;
+    let passwd = entries
+        .iter()
+        .find(|e| e.path == dir.join("escape").join("passwd"))
+        .ok_or_else(|| io::Error::other("missing symlink entry"))?;
+    assert_eq!(passwd.filemode, FileMode::Symlink);
+    assert_eq!(
+        passwd.header.link_name()?.map(|e| e.to_path_buf()),
+        Some(path::PathBuf::from("../../etc/passwd"))
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_hard_links() -> Result<(), io::Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let _cwd_lock = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let original_cwd = env::current_dir()?;
+    let dir = TestDir::new("hard-links")?;
+    let source = dir.join("hello-1.0.0");
+    fs::create_dir_all(&source)?;
+    fs::write(source.join("a"), "hello")?;
+    fs::hard_link(source.join("a"), source.join("b"))?;
+    fs::write(source.join("c"), "hello")?;
+
+    let metadata = fs::metadata(source.join("a"))?;
+    assert_eq!(metadata.nlink(), 2);
+
+    let chandler = Chandler {
+        compression: Some(Compression::Uncompressed),
+        cwd: Some(dir.to_path_buf()),
+        ..Chandler::default()
+    };
+    let walk_result = chandler.walk(&chandler.skip_path_pattern()?, &source);
+    let mut buf = Vec::new();
+    let result = chandler.archive_to(&mut buf, &["hello-1.0.0"]);
+    env::set_current_dir(&original_cwd)?;
+    result?;
+
+    let link_keys: Vec<Option<LinkKey>> = walk_result?
+        .into_iter()
+        .filter(|e| e.filemode == FileMode::File)
+        .map(|e| e.link_key)
+        .collect();
+    let inode = Some(LinkKey::Inode(metadata.dev(), metadata.ino()));
+    assert_eq!(link_keys, vec![inode.clone(), inode, None]);
+
+    let mut observed = Vec::new();
+
+    for entry in tar::Archive::new(buf.as_slice()).entries()? {
+        let entry = entry?;
+        observed.push((
+            entry.path()?.to_string_lossy().to_string(),
+            entry.header().entry_type(),
+            entry.link_name()?.map(|e| e.to_string_lossy().to_string()),
+        ));
+    }
+
+    // Only genuine hard links share content without deduplication.
+    assert_eq!(
+        observed,
+        vec![
+            ("hello-1.0.0".to_string(), tar::EntryType::Directory, None),
+            ("hello-1.0.0/a".to_string(), tar::EntryType::Regular, None),
+            (
+                "hello-1.0.0/b".to_string(),
+                tar::EntryType::Link,
+                Some("hello-1.0.0/a".to_string()),
+            ),
+            ("hello-1.0.0/c".to_string(), tar::EntryType::Regular, None),
+        ]
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unsupported_fifo() -> Result<(), io::Error> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TestDir::new("fifo")?;
+    fs::write(dir.join("hello"), "hello")?;
+
+    let fifo = ffi::CString::new(dir.join("pipe").as_os_str().as_bytes())?;
+
+    if unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let walk = |unsupported: Unsupported| {
+        let chandler = Chandler {
+            unsupported: Some(unsupported),
+            ..Chandler::default()
+        };
+        chandler.walk(&chandler.skip_path_pattern()?, &dir)
+    };
+    let error_result = walk(Unsupported::Error);
+    let skip_result = walk(Unsupported::Skip);
+    let archive_result = walk(Unsupported::Archive);
+
+    assert!(error_result.is_err());
+
+    let skipped: Vec<path::PathBuf> = skip_result?.into_iter().map(|e| e.path).collect();
+    assert!(skipped.contains(&dir.join("hello")));
+    assert!(!skipped.contains(&dir.join("pipe")));
+
+    let archived = archive_result?;
+    let pipe = archived
+        .iter()
+        .find(|e| e.path == dir.join("pipe"))
+        .ok_or_else(|| io::Error::other("missing fifo entry"))?;
+    assert_eq!(pipe.filemode, FileMode::Fifo);
+    assert_eq!(pipe.header.entry_type(), tar::EntryType::Fifo);
+    assert_eq!(pipe.header.device_major()?, Some(0));
+    assert_eq!(pipe.header.device_minor()?, Some(0));
+    Ok(())
+}
+
+#[test]
+fn test_skip_match_relative() -> Result<(), io::Error> {
+    let chandler = Chandler {
+        skip_paths: Some(vec![
+            "^build".to_string(),
+            "tmp".to_string(),
+            "^dist/".to_string(),
+        ]),
+        ..Chandler::default()
+    };
+    let skip_path_pattern = chandler.skip_path_pattern()?;
+    let source = path::Path::new("/tmp/hello");
+    let is_skip = |pth: &str, is_dir: bool| {
+        chandler.is_skip_file(
+            &skip_path_pattern,
+            source,
+            path::Path::new(pth),
+            false,
+            is_dir,
+        )
+    };
+    assert!(!is_skip("/tmp/hello", true)?);
+    assert!(!is_skip("/tmp/hello/src", true)?);
+    assert!(is_skip("/tmp/hello/build", true)?);
+    assert!(!is_skip("/tmp/hello/src/build", true)?);
+    assert!(is_skip("/tmp/hello/src/tmp", true)?);
+    assert!(is_skip("/tmp/hello/dist", true)?);
+    assert!(is_skip("/tmp/hello/dist/hello.o", false)?);
+    assert!(!is_skip("/tmp/hello/dist", false)?);
+    assert!(!is_skip("/tmp/hello/src/dist", true)?);
+
+    // Directory source roots are never skipped, unlike file sources.
+    let is_skip_source = |pth: &str, is_dir: bool| {
+        let pth = path::Path::new(pth);
+        chandler.is_skip_file(&skip_path_pattern, pth, pth, false, is_dir)
+    };
+    assert!(!is_skip_source("/tmp/hello/tmp", true)?);
+    assert!(is_skip_source("/tmp/hello/tmp", false)?);
+    assert!(is_skip_source("build", false)?);
+    assert!(!is_skip_source("hello.c", false)?);
+
+    let is_skip_tracked = |pth: &str| {
+        chandler.is_skip_tracked(
+            &skip_path_pattern,
+            source,
+            source,
+            path::Path::new(pth),
+            false,
+        )
+    };
+    assert!(is_skip_tracked("build/hello.o")?);
+    assert!(is_skip_tracked("src/tmp/hello.o")?);
+    assert!(is_skip_tracked("dist/hello.o")?);
+    assert!(!is_skip_tracked("src/hello.c")?);
+    assert!(!is_skip_tracked("src/dist/hello.o")?);
+    Ok(())
+}
+
+#[test]
+fn test_include() -> Result<(), io::Error> {
+    let chandler = Chandler {
+        include_paths: Some(vec![r"\.md$".to_string(), "^hello/bin/".to_string()]),
+        ..Chandler::default()
+    };
+    let include_path_pattern = chandler.include_path_pattern()?;
+    let entries = vec![
+        test_entry("hello", FileMode::Directory),
+        test_entry("hello/README.md", FileMode::File),
+        test_entry("hello/bin", FileMode::Directory),
+        test_entry("hello/bin/hello", FileMode::File),
+        test_entry("hello/src", FileMode::Directory),
+        test_entry("hello/src/main.c", FileMode::File),
+        test_entry("hello/src/docs", FileMode::Directory),
+        test_entry("hello/src/docs/api.md", FileMode::File),
+    ];
+    let observed: Vec<path::PathBuf> = chandler
+        .include(
+            include_path_pattern.as_ref(),
+            path::Path::new(""),
+            path::Path::new(""),
+            entries,
+        )?
+        .into_iter()
+        .map(|e| e.path)
+        .collect();
+    assert_eq!(
+        observed,
+        [
+            "hello",
+            "hello/README.md",
+            "hello/bin",
+            "hello/bin/hello",
+            "hello/src",
+            "hello/src/docs",
+            "hello/src/docs/api.md",
+        ]
+        .iter()
+        .map(path::PathBuf::from)
+        .collect::<Vec<path::PathBuf>>()
+    );
+
+    // Patterns match relative to source roots, as with skip_paths.
+    let chandler = Chandler {
+        include_paths: Some(vec!["^bin/".to_string()]),
+        ..Chandler::default()
+    };
+    let include_path_pattern = chandler.include_path_pattern()?;
+    let entries = vec![
+        test_entry("src/hello", FileMode::Directory),
+        test_entry("src/hello/README.md", FileMode::File),
+        test_entry("src/hello/bin", FileMode::Directory),
+        test_entry("src/hello/bin/hello", FileMode::File),
+        test_entry("src/hello/lib", FileMode::Directory),
+        test_entry("src/hello/lib/bin", FileMode::Directory),
+        test_entry("src/hello/lib/bin/hello", FileMode::File),
+    ];
+    let observed: Vec<path::PathBuf> = chandler
+        .include(
+            include_path_pattern.as_ref(),
+            path::Path::new("src/hello"),
+            path::Path::new(""),
+            entries,
+        )?
+        .into_iter()
+        .map(|e| e.path)
+        .collect();
+    assert_eq!(
+        observed,
+        ["src/hello", "src/hello/bin", "src/hello/bin/hello"]
+            .iter()
+            .map(path::PathBuf::from)
+            .collect::<Vec<path::PathBuf>>()
+    );
+    assert!(Chandler::default().include_path_pattern()?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_apply_mtime() -> Result<(), io::Error> {
+    let entries = || -> Vec<Entry> {
+        [100, 200, 300]
+            .iter()
+            .map(|mtime| {
+                let mut entry = test_entry(mtime.to_string(), FileMode::File);
+                entry.header.set_mtime(*mtime);
+                entry
+            })
+            .collect()
+    };
+    let mtimes = |entries: &[Entry]| -> Result<Vec<u64>, io::Error> {
+        entries.iter().map(|e| e.header.mtime()).collect()
+    };
+    let mut chandler = Chandler::default();
+
+    let mut preserved = entries();
+    chandler.apply_mtime(&mut preserved, None)?;
+    assert_eq!(mtimes(&preserved)?, vec![100, 200, 300]);
+
+    let mut clamped = entries();
+    chandler.apply_mtime(&mut clamped, Some(250))?;
+    assert_eq!(mtimes(&clamped)?, vec![100, 200, 250]);
+
+    chandler.mtime = Some(Mtime::Replace { epoch: Some(150) });
+    let mut replaced = entries();
+    chandler.apply_mtime(&mut replaced, Some(250))?;
+    assert_eq!(mtimes(&replaced)?, vec![150, 150, 150]);
+
+    chandler.mtime = Some(Mtime::Clamp { epoch: None });
+    assert!(chandler.apply_mtime(&mut entries(), None).is_err());
+
+    chandler.mtime = Some(Mtime::Preserve);
+    let mut preserved = entries();
+    chandler.apply_mtime(&mut preserved, Some(250))?;
+    assert_eq!(mtimes(&preserved)?, vec![100, 200, 300]);
+    Ok(())
+}
+
+#[test]
+fn test_apply_git_mtime() -> Result<(), io::Error> {
+    let _cwd_lock = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = TestDir::new("git-mtime")?;
+    fs::create_dir_all(dir.join("docs"))?;
+    fs::create_dir_all(dir.join("empty"))?;
+    fs::write(dir.join("hello"), "echo hello\n")?;
+    fs::write(dir.join("docs").join("README"), "hello\n")?;
+    fs::write(dir.join("untracked"), "")?;
+
+    let git_commands: &[(&[&str], &str)] = &[
+        (&["init", "-q"], "0 +0000"),
+        (&["add", "hello"], "0 +0000"),
+        (&["commit", "-q", "-m", "hello"], "1000000000 +0000"),
+        (&["add", "docs"], "0 +0000"),
+        (&["commit", "-q", "-m", "docs"], "1100000000 +0000"),
+    ];
+
+    for (args, date) in git_commands {
+        test_git(&dir, args, date)?;
+    }
+
+    let entry = |pth: &str, filemode: FileMode| {
+        let mut entry = test_entry(dir.join(pth), filemode);
+        entry.header.set_mtime(5);
+        (0, entry)
+    };
+    let mut entries = vec![
+        entry("docs", FileMode::Directory),
+        entry("docs/README", FileMode::File),
+        entry("empty", FileMode::Directory),
+        entry("hello", FileMode::File),
+        entry("untracked", FileMode::File),
+    ];
+    let result = Chandler::default().apply_git_mtime(&[&dir], &mut entries);
+
+    // Manifests supply each file as a separate source.
+    let manifest_sources = [
+        dir.join("hello"),
+        dir.join("untracked"),
+        dir.join("docs/README"),
+    ];
+    let mut manifest_entries: Vec<(usize, Entry)> = manifest_sources
+        .iter()
+        .enumerate()
+        .map(|(i, e)| {
+            let mut entry = test_entry(e, FileMode::File);
+            entry.header.set_mtime(5);
+            (i, entry)
+        })
+        .collect();
+    let manifest_result =
+        Chandler::default().apply_git_mtime(&manifest_sources, &mut manifest_entries);
+    result?;
+    manifest_result?;
+
+    let observed: Vec<u64> = entries
+        .iter()
+        .map(|(_, e)| e.header.mtime())
+        .collect::<Result<Vec<u64>, io::Error>>()?;
+    assert_eq!(observed, vec![1100000000, 1100000000, 5, 1000000000, 5]);
+
+    let manifest_observed: Vec<u64> = manifest_entries
+        .iter()
+        .map(|(_, e)| e.header.mtime())
+        .collect::<Result<Vec<u64>, io::Error>>()?;
+    assert_eq!(manifest_observed, vec![1000000000, 5, 1100000000]);
+    Ok(())
+}
+
+#[test]
+fn test_apply_ownership() -> Result<(), io::Error> {
+    let entries = || -> Vec<Entry> {
+        let mut entry = test_entry("hello", FileMode::File);
+        entry.header.set_uid(501);
+        entry.header.set_gid(20);
+        vec![entry]
+    };
+    let owner = |entries: &[Entry]| -> Result<(u64, u64, String, String), io::Error> {
+        let header = &entries[0].header;
+        Ok((
+            header.uid()?,
+            header.gid()?,
+            header
+                .username()
+                .map_err(io::Error::other)?
+                .unwrap_or_default()
+                .to_string(),
+            header
+                .groupname()
+                .map_err(io::Error::other)?
+                .unwrap_or_default()
+                .to_string(),
+        ))
+    };
+    let mut chandler = Chandler::default();
+
+    let mut anonymized = entries();
+    chandler.apply_ownership(&mut anonymized)?;
+    assert_eq!(
+        owner(&anonymized)?,
+        (0, 0, "root".to_string(), "root".to_string())
+    );
+
+    chandler.ownership = Some(Ownership::Map {
+        uids: Some([("501".to_string(), 1000)].into_iter().collect()),
+        gids: None,
+    });
+    let mut mapped = entries();
+    chandler.apply_ownership(&mut mapped)?;
+    assert_eq!(owner(&mapped)?, (1000, 20, String::new(), String::new()));
+
+    // Invalid IDs are rejected, even when no entry matches.
+    chandler.ownership = Some(Ownership::Map {
+        uids: None,
+        gids: Some([("staff".to_string(), 20)].into_iter().collect()),
+    });
+    assert!(chandler.apply_ownership(&mut []).is_err());
+
+    chandler.ownership = Some(Ownership::Preserve);
+    let mut preserved = entries();
+    chandler.apply_ownership(&mut preserved)?;
+    assert_eq!(owner(&preserved)?.0, 501);
+    Ok(())
+}
+
+#[test]
+fn test_limit_permissions() -> Result<(), io::Error> {
+    let chandler = Chandler {
+        max_permissions: Some(0o755),
+        special_paths: Some(vec!["^hello/bin/sudo$".to_string()]),
+        ..Chandler::default()
+    };
+    let special_path_pattern = generate_include_path_pattern(&["^hello/bin/sudo$".to_string()])
+        .map_err(io::Error::other)?;
+    let limit = |filemode: FileMode, pth: &str, mode: u32| -> Result<u32, io::Error> {
+        let mut header = tar::Header::new_ustar();
+        header.set_mode(mode);
+        chandler.limit_permissions(Some(&special_path_pattern), filemode, pth, &mut header)?;
+        header.mode()
+    };
+    assert_eq!(limit(FileMode::File, "hello/bin/hello", 0o4777)?, 0o755);
+    assert_eq!(limit(FileMode::File, "hello/bin/sudo", 0o4777)?, 0o4755);
+    assert_eq!(limit(FileMode::Directory, "hello/tmp", 0o1777)?, 0o755);
+    assert_eq!(limit(FileMode::Symlink, "hello/lib", 0o777)?, 0o777);
+
+    // Special paths strip special bits elsewhere, even without a ceiling.
+    let chandler = Chandler {
+        special_paths: Some(vec!["^hello/bin/sudo$".to_string()]),
+        ..Chandler::default()
+    };
+    let limit = |filemode: FileMode, pth: &str, mode: u32| -> Result<u32, io::Error> {
+        let mut header = tar::Header::new_ustar();
+        header.set_mode(mode);
+        chandler.limit_permissions(Some(&special_path_pattern), filemode, pth, &mut header)?;
+        header.mode()
+    };
+    assert_eq!(limit(FileMode::File, "hello/bin/hello", 0o4777)?, 0o777);
+    assert_eq!(limit(FileMode::File, "hello/bin/sudo", 0o4777)?, 0o4777);
+    assert_eq!(limit(FileMode::Directory, "hello/tmp", 0o1777)?, 0o777);
+    assert_eq!(
+        permission_bit_names(0o4022),
+        vec!["setuid", "group write", "other write"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_prefix_and_tarbomb() -> Result<(), io::Error> {
+    let entry = |pth: &str, mtime: u64| {
+        let mut entry = test_entry(pth, FileMode::File);
+        entry.header.set_mtime(mtime);
+        entry
+    };
+    let mut chandler = Chandler {
+        tarbomb: Some(Tarbomb::Error),
+        ..Chandler::default()
+    };
+    let entries = vec![entry("LICENSE", 2), entry("bin/hello", 3)];
+    assert!(chandler.check_tarbomb(&entries).is_err());
+
+    chandler.prefix = Some("dist/hello-1.0.0/".to_string());
+    assert!(chandler.check_tarbomb(&entries).is_ok());
+
+    let prefix = chandler
+        .prefix_path()
+        .ok_or_else(|| io::Error::other("missing prefix"))?;
+    let prefix_entries = chandler.prefix_entries(&prefix, &entries)?;
+    assert_eq!(
+        prefix_entries
+            .iter()
+            .map(|e| (e.path.to_string_lossy().to_string(), e.filemode))
+            .collect::<Vec<(String, FileMode)>>(),
+        vec![
+            ("dist".to_string(), FileMode::Directory),
+            ("dist/hello-1.0.0".to_string(), FileMode::Directory),
+        ]
+    );
+    assert_eq!(prefix_entries[0].header.mtime()?, 3);
+
+    // Rules match paths before the prefix applies.
+    let chandler = Chandler {
+        compression: Some(Compression::Uncompressed),
+        prefix: Some("hello-1.0.0".to_string()),
+        rules: Some(vec![
+            Rule {
+                permissions: Some(0o644),
+                ..Rule::default()
+            },
+            Rule {
+                when: Some(Condition {
+                    paths: Some("^lib/".to_string()),
+                    ..Condition::default()
+                }),
+                permissions: Some(0o600),
+                ..Rule::default()
+            },
+        ]),
+        ..Chandler::default()
+    };
+    let mut entries = vec![
+        test_entry("README", FileMode::File),
+        test_entry("lib/libhello.so", FileMode::File),
+    ];
+
+    for entry in &mut entries {
+        entry.header.set_size(0);
+    }
+
+    let mut buf = Vec::new();
+    chandler.write_to(Box::new(&mut buf), None, entries)?;
+
+    let mut observed = Vec::new();
+
+    for entry in tar::Archive::new(buf.as_slice()).entries()? {
+        let entry = entry?;
+        observed.push((
+            entry.path()?.to_string_lossy().to_string(),
+            entry.header().mode()?,
+        ));
+    }
+
+    assert_eq!(
+        observed,
+        vec![
+            ("hello-1.0.0".to_string(), 0o755),
+            ("hello-1.0.0/README".to_string(), 0o644),
+            ("hello-1.0.0/lib/libhello.so".to_string(), 0o600),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resolve_compression() -> Result<(), io::Error> {
+    let mut chandler = Chandler::default();
+    assert_eq!(
+        chandler.resolve_compression(path::Path::new("hello-1.0.0.tar.xz"))?,
+        Compression::Xz { level: None }
+    );
+    assert_eq!(
+        chandler.resolve_compression(path::Path::new("hello-1.0.0"))?,
+        DEFAULT_COMPRESSION
+    );
+
+    chandler.compression = Some(Compression::Xz { level: Some(9) });
+    assert_eq!(
+        chandler.resolve_compression(path::Path::new("hello-1.0.0.TXZ"))?,
+        Compression::Xz { level: Some(9) }
+    );
+    assert!(
+        chandler
+            .resolve_compression(path::Path::new("hello-1.0.0.tgz"))
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_normalize_pax_global_header() -> Result<(), io::Error> {
+    let dir = TestDir::new("pax")?;
+    let source = dir.join("source.tar");
+    let mut global_header = tar::Header::new_ustar();
+    global_header.set_entry_type(tar::EntryType::XGlobalHeader);
+    global_header.set_mode(0o666);
+    let mut header = tar::Header::new_ustar();
+    header.set_mode(0o644);
+    test_tarball(
+        &source,
+        &[
+            (
+                &global_header,
+                "pax_global_header",
+                b"52 comment=0123456789abcdef0123456789abcdef01234567\n",
+            ),
+            (&header, "hello-1.0.0/README", b"hi"),
+        ],
+    )?;
+
+    let chandler = Chandler {
+        compression: Some(Compression::Uncompressed),
+        ..Chandler::default()
+    };
+    let mut buf = Vec::new();
+    chandler.normalize_to(&mut buf, &[&source])?;
+
+    let mut observed = Vec::new();
+
+    for entry in tar::Archive::new(buf.as_slice()).entries()? {
+        observed.push(entry?.path()?.to_string_lossy().to_string());
+    }
+
+    assert_eq!(observed, vec!["hello-1.0.0/README".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_skip_ancestors() -> Result<(), io::Error> {
+    let _cwd_lock = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let chandler = Chandler {
+        compression: Some(Compression::Uncompressed),
+        skip_paths: Some(vec!["node_modules".to_string()]),
+        ..Chandler::default()
+    };
+    let entry_paths = |buf: &[u8]| -> Result<Vec<String>, io::Error> {
+        let mut observed = Vec::new();
+
+        for entry in tar::Archive::new(buf).entries()? {
+            observed.push(entry?.path()?.to_string_lossy().to_string());
+        }
+
+        Ok(observed)
+    };
+
+    let dir = TestDir::new("skip-ancestors")?;
+    let source = dir.join("source.tar");
+    let mut header = tar::Header::new_ustar();
+    header.set_mode(0o644);
+    test_tarball(
+        &source,
+        &[
+            (&header, "nm/index.js", b"hi"),
+            (&header, "nm/node_modules/x/a.js", b"hi"),
+        ],
+    )?;
+
+    let mut buf = Vec::new();
+    chandler.normalize_to(&mut buf, &[&source])?;
+    assert_eq!(entry_paths(&buf)?, vec!["nm/index.js".to_string()]);
+
+    // Manifest paths are relative to the current working directory.
+    let dir = TestDir::new_in(path::Path::new("target"), "skip-ancestors")?;
+    fs::create_dir_all(dir.join("node_modules").join("x"))?;
+    fs::write(dir.join("index.js"), "hi")?;
+    fs::write(dir.join("node_modules").join("x").join("a.js"), "hi")?;
+
+    let mut buf = Vec::new();
+    chandler.archive_paths_to(
+        &mut buf,
+        [
+            dir.join("index.js"),
+            dir.join("node_modules").join("x").join("a.js"),
+        ],
+    )?;
+    assert_eq!(
+        entry_paths(&buf)?,
+        vec![dir.join("index.js").to_string_lossy().to_string()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_normalize_sorts_archive_entries() -> Result<(), io::Error> {
+    let dir = TestDir::new("normalize")?;
+    let source = dir.join("source.tar");
+    let target = dir.join("target.tgz");
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o600);
+    test_tarball(
+        &source,
+        &[
+            (&header, "hello-1.0.0/hello.bat", b"hi"),
+            (&header, "hello-1.0.0/README", b"hi"),
+            (&header, ".DS_Store", b"hi"),
+        ],
+    )?;
+    Chandler::default().normalize(&target, &[&source])?;
+
+    let mut buf = Vec::new();
+    Chandler::default().normalize_to(&mut buf, &[&source])?;
+    assert_eq!(buf, fs::read(&target)?);
+
+    let mut archive = open_archive(&target)?;
+    let mut observed = Vec::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        observed.push((
+            entry.path()?.to_string_lossy().to_string(),
+            entry.header().mode()?,
+        ));
     }
+
+    assert_eq!(
+        observed,
+        vec![
+            ("hello-1.0.0/README".to_string(), 0o644),
+            ("hello-1.0.0/hello.bat".to_string(), 0o644),
+        ]
+    );
+    Ok(())
 }